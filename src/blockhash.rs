@@ -0,0 +1,139 @@
+use {
+    crate::{print_shell_vars, Context, OutputFormat},
+    clap::{Arg, ArgMatches, Command},
+    serde::{Deserialize, Serialize},
+    solana_client::{
+        rpc_request::RpcRequest,
+        rpc_response::{Response, RpcBlockhash},
+    },
+    solana_sdk::{
+        clock::{DEFAULT_MS_PER_SLOT, MAX_PROCESSING_AGE},
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+    },
+    std::{
+        fs,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+pub fn command() -> Command<'static> {
+    Command::new("blockhash")
+        .about("Print the latest blockhash and the slot it was fetched at")
+        .arg(
+            Arg::new("write_blockhash")
+                .long("write-blockhash")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "Also write the blockhash and its last valid block height to this file, \
+                     for `send --blockhash PATH` to sign with on another machine",
+                ),
+        )
+}
+
+/// A blockhash written by `--write-blockhash`
+#[derive(Serialize, Deserialize)]
+pub struct BlockhashFile {
+    pub blockhash: String,
+    pub last_valid_block_height: u64,
+    pub slot: u64,
+    /// Unix time the blockhash was fetched at, in seconds
+    pub fetched_at: u64,
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A blockhash read back from a `--write-blockhash` file
+pub struct StoredBlockhash {
+    pub blockhash: Hash,
+    pub last_valid_block_height: u64,
+    /// How long ago it was fetched
+    pub age: Duration,
+}
+
+impl StoredBlockhash {
+    /// Whether the blockhash is old enough that it may have expired. Only its age is considered,
+    /// so this works offline
+    pub fn may_have_expired(&self) -> bool {
+        self.age > Duration::from_millis(MAX_PROCESSING_AGE as u64 * DEFAULT_MS_PER_SLOT)
+    }
+}
+
+/// Reads a `--write-blockhash` file
+pub fn read_blockhash_file(path: &str) -> Result<StoredBlockhash, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("unable to read {path}: {err}"))?;
+    let file = serde_json::from_str::<BlockhashFile>(&contents)
+        .map_err(|err| format!("{path} is not a blockhash file: {err}"))?;
+    let blockhash = file
+        .blockhash
+        .parse::<Hash>()
+        .map_err(|err| format!("invalid blockhash in {path}: {err}"))?;
+    Ok(StoredBlockhash {
+        blockhash,
+        last_valid_block_height: file.last_valid_block_height,
+        age: Duration::from_secs(unix_time().saturating_sub(file.fetched_at)),
+    })
+}
+
+/// Fetches the latest blockhash. The raw request is used since the client's helpers drop the slot
+pub async fn run(
+    context: &Context,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Response {
+        context: rpc_context,
+        value,
+    } = context
+        .rpc_client()
+        .call("getLatestBlockhash", |rpc_client| {
+            rpc_client.send::<Response<RpcBlockhash>>(
+                RpcRequest::GetLatestBlockhash,
+                serde_json::json!([CommitmentConfig::confirmed()]),
+            )
+        })
+        .await
+        .map_err(|err| format!("error: unable to get latest blockhash: {err}"))?;
+
+    if let Some(path) = matches.value_of("write_blockhash") {
+        let file = BlockhashFile {
+            blockhash: value.blockhash.clone(),
+            last_valid_block_height: value.last_valid_block_height,
+            slot: rpc_context.slot,
+            fetched_at: unix_time(),
+        };
+        fs::write(path, serde_json::to_string_pretty(&file)?)
+            .map_err(|err| format!("error: unable to write {path}: {err}"))?;
+    }
+
+    match context.output_format {
+        OutputFormat::Text => {
+            println!("Blockhash: {}", value.blockhash);
+            println!("Slot: {}", rpc_context.slot);
+            println!("Last valid block height: {}", value.last_valid_block_height);
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "blockhash": value.blockhash,
+                "slot": rpc_context.slot,
+                "last_valid_block_height": value.last_valid_block_height,
+            })
+        ),
+        OutputFormat::Shell => print_shell_vars(&[
+            ("BLOCKHASH", value.blockhash),
+            ("SLOT", rpc_context.slot.to_string()),
+            (
+                "LAST_VALID_BLOCK_HEIGHT",
+                value.last_valid_block_height.to_string(),
+            ),
+        ]),
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use {
+    crate::{print_shell_vars, Context, OutputFormat, MEMO_PROGRAM_ID},
+    clap::{crate_version, Arg, ArgMatches, Command},
+    solana_client::{
+        client_error::ClientErrorKind,
+        rpc_request::{RpcError, RpcRequest},
+    },
+    solana_sdk::{account::Account, feature, feature_set, pubkey::Pubkey},
+};
+
+/// JSON RPC error code for a method the node doesn't know
+const JSON_RPC_METHOD_NOT_FOUND: i64 = -32601;
+
+/// What each feature can be required as, and the send arguments that depend on it
+const FEATURES: [(&str, &str); 4] = [
+    (
+        "priority-fees",
+        "--compute-unit-price, --priority-fee-auto, --escalate-fee",
+    ),
+    ("priority-fee-auto", "--priority-fee-auto"),
+    ("memo", "--memo-template, --idempotency-key, --pad-to-size"),
+    ("token", "--mint, --create-ata"),
+];
+
+pub fn command() -> Command<'static> {
+    Command::new("doctor")
+        .about("Report which burri features the cluster supports")
+        .arg(
+            Arg::new("require")
+                .long("require")
+                .value_name("FEATURE")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .possible_values(FEATURES.map(|(name, _)| name))
+                .help("Exit with an error if the cluster doesn't support FEATURE"),
+        )
+}
+
+/// Whether the cluster supports one feature, and why not if it doesn't
+struct Check {
+    name: &'static str,
+    flags: &'static str,
+    unsupported: Option<String>,
+}
+
+fn is_program(account: &Option<Account>) -> bool {
+    account.as_ref().is_some_and(|account| account.executable)
+}
+
+pub async fn run(
+    context: &Context,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc_client = context.rpc_client();
+    let version = rpc_client
+        .call("getVersion", |rpc_client| rpc_client.get_version())
+        .await
+        .map_err(|err| format!("error: unable to get node version: {err}"))?;
+
+    let addresses = [
+        feature_set::add_set_compute_unit_price_ix::id(),
+        MEMO_PROGRAM_ID,
+        spl_token::id(),
+        spl_associated_token_account::id(),
+    ];
+    let accounts = rpc_client
+        .call("getMultipleAccounts", |rpc_client| {
+            rpc_client.get_multiple_accounts(&addresses)
+        })
+        .await
+        .map_err(|err| format!("error: unable to get accounts: {err}"))?;
+    let missing_program = |address: &Pubkey| format!("program {address} is not deployed");
+
+    let priority_fees = match accounts[0].as_ref().and_then(feature::from_account) {
+        Some(feature::Feature {
+            activated_at: Some(_),
+        }) => None,
+        _ => Some(format!(
+            "feature {} is not active",
+            feature_set::add_set_compute_unit_price_ix::id()
+        )),
+    };
+    // Older nodes don't serve the method at all, which is the only error that matters here
+    let priority_fee_auto = match rpc_client
+        .call("getRecentPrioritizationFees", |rpc_client| {
+            rpc_client.send::<serde_json::Value>(
+                RpcRequest::Custom {
+                    method: "getRecentPrioritizationFees",
+                },
+                serde_json::json!([]),
+            )
+        })
+        .await
+    {
+        Err(err)
+            if matches!(
+                err.kind(),
+                ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+                    if *code == JSON_RPC_METHOD_NOT_FOUND
+            ) =>
+        {
+            Some(format!(
+                "node {} doesn't serve getRecentPrioritizationFees",
+                version.solana_core
+            ))
+        }
+        _ => priority_fees.clone(),
+    };
+    let memo = (!is_program(&accounts[1])).then(|| missing_program(&MEMO_PROGRAM_ID));
+    let token = if !is_program(&accounts[2]) {
+        Some(missing_program(&spl_token::id()))
+    } else if !is_program(&accounts[3]) {
+        Some(missing_program(&spl_associated_token_account::id()))
+    } else {
+        None
+    };
+
+    let checks = FEATURES
+        .into_iter()
+        .zip([priority_fees, priority_fee_auto, memo, token])
+        .map(|((name, flags), unsupported)| Check {
+            name,
+            flags,
+            unsupported,
+        })
+        .collect::<Vec<_>>();
+
+    match context.output_format {
+        OutputFormat::Text => {
+            println!("burri version: {}", crate_version!());
+            match version.feature_set {
+                Some(feature_set) => println!(
+                    "Node version: {} (feature set {feature_set})",
+                    version.solana_core
+                ),
+                None => println!("Node version: {}", version.solana_core),
+            }
+            for check in &checks {
+                match &check.unsupported {
+                    None => println!("{}: supported ({})", check.name, check.flags),
+                    Some(reason) => {
+                        println!("{}: unsupported, {reason} ({})", check.name, check.flags)
+                    }
+                }
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "burri_version": crate_version!(),
+                "node_version": version.solana_core,
+                "feature_set": version.feature_set,
+                "features": checks
+                    .iter()
+                    .map(|check| serde_json::json!({
+                        "name": check.name,
+                        "supported": check.unsupported.is_none(),
+                        "reason": check.unsupported,
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        ),
+        OutputFormat::Shell => {
+            let mut vars = vec![
+                ("VERSION", crate_version!().to_string()),
+                ("NODE_VERSION", version.solana_core.clone()),
+            ];
+            if let Some(feature_set) = version.feature_set {
+                vars.push(("FEATURE_SET", feature_set.to_string()));
+            }
+            let names = checks
+                .iter()
+                .map(|check| format!("FEATURE_{}", check.name.replace('-', "_").to_uppercase()))
+                .collect::<Vec<_>>();
+            for (name, check) in names.iter().zip(&checks) {
+                vars.push((name, check.unsupported.is_none().to_string()));
+            }
+            print_shell_vars(&vars);
+        }
+    }
+
+    let unsupported = matches
+        .values_of("require")
+        .into_iter()
+        .flatten()
+        .filter(|required| {
+            checks
+                .iter()
+                .any(|check| check.name == *required && check.unsupported.is_some())
+        })
+        .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        return Err(format!(
+            "error: the cluster doesn't support {}",
+            unsupported.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
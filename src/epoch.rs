@@ -0,0 +1,45 @@
+use {
+    crate::{print_shell_vars, Context, OutputFormat},
+    clap::Command,
+};
+
+pub fn command() -> Command<'static> {
+    Command::new("epoch").about("Print the current epoch, slot and block height")
+}
+
+pub async fn run(context: &Context) -> Result<(), Box<dyn std::error::Error>> {
+    let epoch_info = context
+        .rpc_client()
+        .call("getEpochInfo", |rpc_client| rpc_client.get_epoch_info())
+        .await
+        .map_err(|err| format!("error: unable to get epoch info: {err}"))?;
+
+    match context.output_format {
+        OutputFormat::Text => {
+            println!(
+                "Epoch: {} (slot {} of {})",
+                epoch_info.epoch, epoch_info.slot_index, epoch_info.slots_in_epoch
+            );
+            println!("Slot: {}", epoch_info.absolute_slot);
+            println!("Block height: {}", epoch_info.block_height);
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "epoch": epoch_info.epoch,
+                "slot_index": epoch_info.slot_index,
+                "slots_in_epoch": epoch_info.slots_in_epoch,
+                "slot": epoch_info.absolute_slot,
+                "block_height": epoch_info.block_height,
+            })
+        ),
+        OutputFormat::Shell => print_shell_vars(&[
+            ("EPOCH", epoch_info.epoch.to_string()),
+            ("SLOT_INDEX", epoch_info.slot_index.to_string()),
+            ("SLOTS_IN_EPOCH", epoch_info.slots_in_epoch.to_string()),
+            ("SLOT", epoch_info.absolute_slot.to_string()),
+            ("BLOCK_HEIGHT", epoch_info.block_height.to_string()),
+        ]),
+    }
+    Ok(())
+}
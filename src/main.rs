@@ -1,28 +1,75 @@
+mod blockhash;
+mod doctor;
+mod epoch;
+mod metrics;
+mod rate_limiter;
+mod rpc;
+mod selftest;
+mod send;
+mod watch;
+
 use {
-    clap::{crate_description, crate_name, crate_version, Arg, Command},
-    rand::Rng,
+    bip39::{Language, Mnemonic, Seed},
+    clap::{crate_description, crate_name, crate_version, Arg, ArgMatches, Command},
+    reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, USER_AGENT},
+    rpc::FailoverRpcClient,
     solana_clap_v3_utils::{
-        input_parsers::pubkeys_of,
+        input_parsers::value_of,
         input_validators::{
-            is_url_or_moniker, is_valid_pubkey, is_valid_signer, normalize_to_url_if_moniker,
+            is_derivation, is_url_or_moniker, is_valid_signer, normalize_to_url_if_moniker,
         },
         keypair::DefaultSigner,
     },
-    solana_client::nonblocking::rpc_client::RpcClient,
     solana_remote_wallet::remote_wallet::RemoteWalletManager,
     solana_sdk::{
         commitment_config::CommitmentConfig,
+        derivation_path::DerivationPath,
         instruction::{AccountMeta, Instruction},
-        message::Message,
-        native_token::Sol,
+        program_utils::limited_deserialize,
+        pubkey,
         pubkey::Pubkey,
+        signature::{keypair_from_seed_and_derivation_path, Keypair, Signature},
+        signer::{null_signer::NullSigner, Signer},
         system_instruction, system_program,
         transaction::Transaction,
     },
-    std::{process::exit, sync::Arc},
+    std::{
+        process::exit,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::sync::Semaphore,
+    url::{form_urlencoded, Host, Url},
+    zeroize::Zeroizing,
 };
 
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Longest memo that fits in a single-signer transaction
+pub const MAX_MEMO_LEN: usize = 566;
+
+/// Expands the `{i}`, `{timestamp}` and `{amount}` placeholders in a memo template
+pub fn expand_memo_template(template: &str, iteration: usize, lamports: u64) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    template
+        .replace("{i}", &iteration.to_string())
+        .replace("{timestamp}", &timestamp.to_string())
+        .replace("{amount}", &lamports.to_string())
+}
+
+pub fn memo_instruction(memo: &str) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![],
+        data: memo.as_bytes().to_vec(),
+    }
+}
+
 pub fn transfer_with(
+    program_id: &Pubkey,
     from_pubkey: &Pubkey,
     to_pubkey: &Pubkey,
     lamports: u64,
@@ -37,17 +84,345 @@ pub fn transfer_with(
     }
 
     Instruction::new_with_bincode(
-        system_program::id(),
+        *program_id,
         &system_instruction::SystemInstruction::Transfer { lamports },
         account_metas,
     )
 }
 
+/// Returns a Solana Explorer link for `signature`, or `None` if `json_rpc_url` doesn't map onto a
+/// cluster the explorer knows how to display
+pub fn explorer_url(json_rpc_url: &str, signature: &Signature) -> Option<String> {
+    let url = Url::parse(json_rpc_url).ok()?;
+    let cluster = match url.host_str()? {
+        "api.mainnet-beta.solana.com" => String::new(),
+        "api.devnet.solana.com" => "?cluster=devnet".into(),
+        "api.testnet.solana.com" => "?cluster=testnet".into(),
+        "localhost" | "127.0.0.1" => format!(
+            "?cluster=custom&customUrl={}",
+            form_urlencoded::byte_serialize(json_rpc_url.as_bytes()).collect::<String>()
+        ),
+        _ => return None,
+    };
+    Some(format!(
+        "https://explorer.solana.com/tx/{signature}{cluster}"
+    ))
+}
+
+/// Hostnames of well-known mainnet-beta RPC providers
+const MAINNET_HOSTS: [&str; 5] = [
+    "api.mainnet-beta.solana.com",
+    "solana-api.projectserum.com",
+    "mainnet.helius-rpc.com",
+    "solana-mainnet.g.alchemy.com",
+    "rpc.ankr.com",
+];
+
+/// Names the cluster that `json_rpc_url` belongs to, going by the public RPC hostnames
+pub fn cluster_name(json_rpc_url: &str) -> &'static str {
+    let url = match Url::parse(json_rpc_url) {
+        Ok(url) => url,
+        Err(_) => return "an unknown cluster",
+    };
+    match url.host_str() {
+        Some(host) if MAINNET_HOSTS.contains(&host) => "mainnet-beta",
+        Some("api.devnet.solana.com") => "devnet",
+        Some("api.testnet.solana.com") => "testnet",
+        Some("localhost" | "127.0.0.1") => "a local cluster",
+        _ => "an unknown cluster",
+    }
+}
+
+/// Renders `transaction` as JSON, decoding system program instructions so their fields are readable
+pub fn transaction_to_json(transaction: &Transaction) -> serde_json::Value {
+    let message = &transaction.message;
+    let instructions = message
+        .instructions
+        .iter()
+        .map(|instruction| {
+            let program_id = message.account_keys[instruction.program_id_index as usize];
+            let accounts = instruction
+                .accounts
+                .iter()
+                .map(|index| {
+                    let index = *index as usize;
+                    serde_json::json!({
+                        "pubkey": message.account_keys[index].to_string(),
+                        "signer": message.is_signer(index),
+                        "writable": message.is_writable(index),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let parsed = if program_id == system_program::id() {
+                limited_deserialize::<system_instruction::SystemInstruction>(&instruction.data)
+                    .ok()
+                    .and_then(|system_instruction| serde_json::to_value(system_instruction).ok())
+            } else {
+                None
+            };
+            serde_json::json!({
+                "programId": program_id.to_string(),
+                "accounts": accounts,
+                "data": base64::encode(&instruction.data),
+                "parsed": parsed,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "signatures": transaction
+            .signatures
+            .iter()
+            .map(|signature| signature.to_string())
+            .collect::<Vec<_>>(),
+        "message": {
+            "header": {
+                "numRequiredSignatures": message.header.num_required_signatures,
+                "numReadonlySignedAccounts": message.header.num_readonly_signed_accounts,
+                "numReadonlyUnsignedAccounts": message.header.num_readonly_unsigned_accounts,
+            },
+            "accountKeys": message
+                .account_keys
+                .iter()
+                .map(|account_key| account_key.to_string())
+                .collect::<Vec<_>>(),
+            "recentBlockhash": message.recent_blockhash.to_string(),
+            "instructions": instructions,
+        },
+    })
+}
+
+/// Renders `instructions` as JSON, in the same shape as the instructions of `transaction_to_json`
+/// but with each account's flags as the instruction gives them
+pub fn instructions_to_json(instructions: &[Instruction]) -> serde_json::Value {
+    instructions
+        .iter()
+        .map(|instruction| {
+            serde_json::json!({
+                "programId": instruction.program_id.to_string(),
+                "accounts": instruction
+                    .accounts
+                    .iter()
+                    .map(|account| serde_json::json!({
+                        "pubkey": account.pubkey.to_string(),
+                        "signer": account.is_signer,
+                        "writable": account.is_writable,
+                    }))
+                    .collect::<Vec<_>>(),
+                "data": base64::encode(&instruction.data),
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Shell,
+}
+
+impl OutputFormat {
+    fn from_str(s: &str) -> Self {
+        match s {
+            "json" => Self::Json,
+            "shell" => Self::Shell,
+            _ => Self::Text,
+        }
+    }
+}
+
+/// Prints `vars` as `BURRI_<NAME>='<value>'` lines for `--output shell`. Values are single-quoted,
+/// so they are safe to `eval` whatever they contain
+pub fn print_shell_vars(vars: &[(&str, String)]) {
+    for (name, value) in vars {
+        println!("BURRI_{name}='{}'", value.replace('\'', r"'\''"));
+    }
+}
+
+/// Reads a BIP39 mnemonic phrase from stdin and derives a keypair from it. The phrase and the seed
+/// derived from it are zeroed once the keypair has been derived
+fn keypair_from_mnemonic_stdin(
+    passphrase: &str,
+    derivation_path: DerivationPath,
+) -> Result<Keypair, Box<dyn std::error::Error>> {
+    let mut phrase = Zeroizing::new(String::new());
+    std::io::stdin()
+        .read_line(&mut phrase)
+        .map_err(|err| format!("unable to read mnemonic phrase from stdin: {err}"))?;
+    let words = Zeroizing::new(phrase.split_whitespace().collect::<Vec<_>>().join(" "));
+    let mnemonic = Mnemonic::from_phrase(&words, Language::English)
+        .map_err(|err| format!("invalid mnemonic phrase: {err}"))?;
+    let seed = Seed::new(&mnemonic, passphrase);
+    keypair_from_seed_and_derivation_path(seed.as_bytes(), Some(derivation_path))
+}
+
+/// Reads a base58-encoded 64-byte secret key from stdin. The string and the decoded bytes are
+/// zeroed once the keypair has been built
+fn keypair_from_base58_stdin() -> Result<Keypair, Box<dyn std::error::Error>> {
+    let mut secret = Zeroizing::new(String::new());
+    std::io::stdin()
+        .read_line(&mut secret)
+        .map_err(|err| format!("unable to read secret key from stdin: {err}"))?;
+    let bytes = Zeroizing::new(
+        bs58::decode(secret.trim())
+            .into_vec()
+            .map_err(|err| format!("invalid base58 secret key: {err}"))?,
+    );
+    Keypair::from_bytes(&bytes).map_err(|err| format!("invalid secret key: {err}").into())
+}
+
+/// For `--strict-url`, fails if `url` is plain http to anywhere but this machine
+pub fn check_strict_url(url: &Url) -> Result<(), String> {
+    let local = match url.host() {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(address)) => address.is_loopback(),
+        Some(Host::Ipv6(address)) => address.is_loopback(),
+        None => false,
+    };
+    if url.scheme() == "http" && !local {
+        return Err(format!(
+            "error: {url} is not encrypted, use https:// or drop --strict-url"
+        ));
+    }
+    Ok(())
+}
+
+/// Parses a `NAME:VALUE` HTTP header. The value is marked sensitive so it's never printed
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("error: invalid header {header:?}, expected NAME:VALUE"))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|err| format!("error: invalid header name {name:?}: {err}"))?;
+    let mut value = HeaderValue::from_str(value.trim())
+        .map_err(|err| format!("error: invalid value for header {name}: {err}"))?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Loads the `--config` file. An http(s) URL is fetched, so containers needn't have the config
+/// baked in; anything else is a local path, where a missing or unreadable file means the defaults
+async fn load_config(
+    config_file: &str,
+) -> Result<solana_cli_config::Config, Box<dyn std::error::Error>> {
+    match Url::parse(config_file) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+            let config = reqwest::get(url)
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| format!("error: unable to fetch config {config_file}: {err}"))?
+                .text()
+                .await
+                .map_err(|err| format!("error: unable to fetch config {config_file}: {err}"))?;
+            serde_yaml::from_str(&config)
+                .map_err(|err| format!("error: invalid config {config_file}: {err}").into())
+        }
+        _ => Ok(solana_cli_config::Config::load(config_file).unwrap_or_default()),
+    }
+}
+
+/// Settings shared by every subcommand, taken from the global arguments
+pub struct Context {
+    pub cli_config: solana_cli_config::Config,
+    pub json_rpc_urls: Vec<String>,
+    pub rpc_headers: HeaderMap,
+    /// `--max-concurrent-rpc`, shared by every client
+    pub rpc_limit: Option<Arc<Semaphore>>,
+    pub output_format: OutputFormat,
+    pub verbose: bool,
+    /// `--strict-url`
+    pub strict_url: bool,
+}
+
+impl Context {
+    pub fn rpc_client(&self) -> FailoverRpcClient {
+        self.rpc_client_with_commitment(CommitmentConfig::confirmed())
+    }
+
+    /// A client whose calls, and waits for confirmation, default to `commitment_config`
+    pub fn rpc_client_with_commitment(
+        &self,
+        commitment_config: CommitmentConfig,
+    ) -> FailoverRpcClient {
+        FailoverRpcClient::new(
+            &self.json_rpc_urls,
+            &self.rpc_headers,
+            self.rpc_limit.clone(),
+            commitment_config,
+            self.verbose,
+        )
+    }
+
+    /// Resolves the fee payer from `--mnemonic-stdin`, `--keypair-base58-stdin`, `--keypair` or the
+    /// configuration file. With `allow_null_signer`, `--keypair` may be an address, giving a signer
+    /// that can't sign
+    pub fn signer(
+        &self,
+        matches: &ArgMatches,
+        wallet_manager: &mut Option<Arc<RemoteWalletManager>>,
+        allow_null_signer: bool,
+    ) -> Result<Box<dyn Signer>, Box<dyn std::error::Error>> {
+        if matches.is_present("strict_signer")
+            && !matches.is_present("keypair")
+            && !matches.is_present("mnemonic_stdin")
+            && !matches.is_present("keypair_base58_stdin")
+        {
+            eprintln!("error: --strict-signer requires the signer to be given with --keypair");
+            exit(1);
+        }
+
+        let default_signer = if matches.is_present("mnemonic_stdin") {
+            let passphrase = Zeroizing::new(
+                matches
+                    .value_of("bip39_passphrase")
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+            let derivation_path =
+                DerivationPath::from_key_str(matches.value_of("derivation_path").unwrap_or("0/0"))
+                    .map_err(|err| format!("error: invalid derivation path: {err}"))?;
+            Box::new(
+                keypair_from_mnemonic_stdin(&passphrase, derivation_path).unwrap_or_else(|err| {
+                    eprintln!("error: {err}");
+                    exit(1);
+                }),
+            )
+        } else if matches.is_present("keypair_base58_stdin") {
+            Box::new(keypair_from_base58_stdin().unwrap_or_else(|err| {
+                eprintln!("error: {err}");
+                exit(1);
+            }))
+        } else if let Some(pubkey) = matches
+            .value_of("keypair")
+            .and_then(|keypair| keypair.parse::<Pubkey>().ok())
+            .filter(|_| allow_null_signer)
+        {
+            Box::new(NullSigner::new(&pubkey))
+        } else {
+            DefaultSigner::new(
+                "keypair",
+                matches
+                    .value_of("keypair")
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| self.cli_config.keypair_path.clone()),
+            )
+            .signer_from_path(matches, wallet_manager)
+            .unwrap_or_else(|err| {
+                eprintln!("error: {err}");
+                exit(1);
+            })
+        };
+        Ok(default_signer)
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let matches = Command::new(crate_name!())
         .about(crate_description!())
         .version(crate_version!())
+        .subcommand_required(true)
         .arg_required_else_help(true)
         .arg({
             let arg = Arg::new("config_file")
@@ -56,7 +431,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .value_name("PATH")
                 .takes_value(true)
                 .global(true)
-                .help("Configuration file to use");
+                .help("Configuration file to use, or an http(s) URL to fetch it from");
             if let Some(ref config_file) = *solana_cli_config::CONFIG_FILE {
                 arg.default_value(config_file)
             } else {
@@ -66,114 +441,270 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .arg(
             Arg::new("keypair")
                 .long("keypair")
+                .global(true)
                 .value_name("KEYPAIR")
                 .validator(|s| is_valid_signer(s))
                 .takes_value(true)
                 .help("Filepath or URL to a keypair [default: client keypair]"),
         )
+        .arg(
+            Arg::new("mnemonic_stdin")
+                .long("mnemonic-stdin")
+                .global(true)
+                .takes_value(false)
+                .conflicts_with("keypair")
+                .help(
+                    "Derive the fee payer from a BIP39 mnemonic phrase read from stdin \
+                     instead of a keypair file",
+                ),
+        )
+        .arg(
+            Arg::new("keypair_base58_stdin")
+                .long("keypair-base58-stdin")
+                .global(true)
+                .takes_value(false)
+                .conflicts_with_all(&["keypair", "mnemonic_stdin"])
+                .help(
+                    "Read the fee payer's secret key from stdin as a base58 string \
+                     instead of a keypair file",
+                ),
+        )
+        .arg(
+            Arg::new("bip39_passphrase")
+                .long("bip39-passphrase")
+                .global(true)
+                .value_name("PASSPHRASE")
+                .takes_value(true)
+                .requires("mnemonic_stdin")
+                .conflicts_with("keypair")
+                .help("BIP39 passphrase for the mnemonic phrase [default: none]"),
+        )
+        .arg(
+            Arg::new("derivation_path")
+                .long("derivation-path")
+                .global(true)
+                .value_name("ACCOUNT[/CHANGE]")
+                .takes_value(true)
+                .requires("mnemonic_stdin")
+                .conflicts_with("keypair")
+                .validator(|s| is_derivation(s))
+                .help("Derivation path of the fee payer, under m/44'/501' [default: 0/0]"),
+        )
+        .arg(
+            Arg::new("strict_signer")
+                .long("strict-signer")
+                .global(true)
+                .takes_value(false)
+                .help(
+                    "Refuse to fall back to the configuration file's keypair; \
+                     the signer must be given with --keypair",
+                ),
+        )
         .arg(
             Arg::new("verbose")
                 .long("verbose")
+                .global(true)
                 .short('v')
                 .takes_value(false)
                 .help("Show additional information"),
         )
+        .arg(
+            Arg::new("log")
+                .long("log")
+                .value_name("FILTER")
+                .takes_value(true)
+                .global(true)
+                .help(
+                    "Log filter, in env_logger syntax, such as \"solana_client=debug\" or \"off\". \
+                     Takes precedence over RUST_LOG, which in turn takes precedence over the \
+                     default of \"solana=info\". Independent of --verbose",
+                ),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .global(true)
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(["text", "json", "shell"])
+                .default_value("text")
+                .help(
+                    "Format of the result. `shell` prints BURRI_*='...' lines that can be \
+                     sourced or eval'd by a shell script",
+                ),
+        )
         .arg(
             Arg::new("json_rpc_url")
                 .short('u')
                 .long("url")
                 .value_name("URL")
                 .takes_value(true)
+                .multiple_occurrences(true)
                 .global(true)
                 .validator(|s| is_url_or_moniker(s))
-                .help("JSON RPC URL for the cluster [default: value from configuration file]"),
+                .help(
+                    "JSON RPC URL for the cluster. May be given more than once to fail over \
+                     to the next URL when an endpoint can't be reached \
+                     [default: value from configuration file]",
+                ),
         )
         .arg(
-            Arg::new("extra_addresses")
-                .value_name("ADDRESS")
-                .validator(|s| is_valid_pubkey(s))
+            Arg::new("strict_url")
+                .long("strict-url")
+                .global(true)
+                .takes_value(false)
+                .help(
+                    "Refuse plain http:// JSON RPC URLs for anything but localhost, so that \
+                     transactions and --header values aren't sent unencrypted",
+                ),
+        )
+        .arg(
+            Arg::new("header")
+                .long("header")
+                .value_name("NAME:VALUE")
                 .takes_value(true)
-                .multiple(true)
-                .help("Extra addresses to append"),
+                .multiple_occurrences(true)
+                .global(true)
+                .validator(|s| parse_header(s).map(|_| ()))
+                .help(
+                    "HTTP header to send with every JSON RPC request, such as an API key. \
+                     May be given more than once",
+                ),
         )
+        .arg(
+            Arg::new("rpc_token")
+                .long("rpc-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .global(true)
+                .help("Send `Authorization: Bearer TOKEN` with every JSON RPC request"),
+        )
+        .arg(
+            Arg::new("user_agent")
+                .long("user-agent")
+                .value_name("STRING")
+                .takes_value(true)
+                .global(true)
+                .default_value(concat!("burri/", crate_version!()))
+                .help(
+                    "User-Agent to send with every JSON RPC request, so the RPC provider can \
+                     tell burri's requests apart in its logs",
+                ),
+        )
+        .arg(
+            Arg::new("deny_mainnet")
+                .long("deny-mainnet")
+                .takes_value(false)
+                .global(true)
+                .env("BURRI_DENY_MAINNET")
+                .help(
+                    "Refuse to run against mainnet-beta, going by the URL moniker and the \
+                     hostnames of well-known mainnet RPC providers",
+                ),
+        )
+        .arg(
+            Arg::new("max_concurrent_rpc")
+                .long("max-concurrent-rpc")
+                .value_name("N")
+                .takes_value(true)
+                .global(true)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(0) => Err("must be greater than zero".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help(
+                    "Never have more than N JSON RPC requests in flight at once, across every \
+                     endpoint and everything burri is doing in parallel [default: unlimited]",
+                ),
+        )
+        .subcommand(send::command())
+        .subcommand(blockhash::command())
+        .subcommand(epoch::command())
+        .subcommand(doctor::command())
+        .subcommand(watch::command())
+        .subcommand(selftest::command())
         .get_matches();
 
+    if let Some(("selftest", _)) = matches.subcommand() {
+        return selftest::run();
+    }
+
     let mut wallet_manager: Option<Arc<RemoteWalletManager>> = None;
 
     let cli_config = if let Some(config_file) = matches.value_of("config_file") {
-        solana_cli_config::Config::load(config_file).unwrap_or_default()
+        load_config(config_file).await?
     } else {
         solana_cli_config::Config::default()
     };
 
-    let default_signer = DefaultSigner::new(
-        "keypair",
-        matches
-            .value_of("keypair")
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| cli_config.keypair_path.clone()),
-    )
-    .signer_from_path(&matches, &mut wallet_manager)
-    .unwrap_or_else(|err| {
-        eprintln!("error: {err}");
-        exit(1);
-    });
-
-    let json_rpc_url = normalize_to_url_if_moniker(
-        matches
-            .value_of("json_rpc_url")
-            .unwrap_or(&cli_config.json_rpc_url),
-    );
-
-    let verbose = matches.is_present("verbose");
-    let extra_addresses = pubkeys_of(&matches, "extra_addresses").unwrap_or_default();
-
-    solana_logger::setup_with_default("solana=info");
-    if verbose {
-        println!("JSON RPC URL: {json_rpc_url}");
+    let json_rpc_urls = matches
+        .values_of("json_rpc_url")
+        .map(|values| values.collect::<Vec<_>>())
+        .unwrap_or_else(|| vec![&cli_config.json_rpc_url])
+        .into_iter()
+        .map(normalize_to_url_if_moniker)
+        .collect::<Vec<_>>();
+    let strict_url = matches.is_present("strict_url");
+    let deny_mainnet = matches.is_present("deny_mainnet");
+    for json_rpc_url in &json_rpc_urls {
+        let url = Url::parse(json_rpc_url)
+            .map_err(|err| format!("error: invalid JSON RPC URL {json_rpc_url}: {err}"))?;
+        if strict_url {
+            check_strict_url(&url)?;
+        }
+        if deny_mainnet && cluster_name(json_rpc_url) == "mainnet-beta" {
+            return Err(format!(
+                "error: {json_rpc_url} is mainnet-beta, which --deny-mainnet refuses"
+            )
+            .into());
+        }
     }
-    let rpc_client =
-        RpcClient::new_with_commitment(json_rpc_url.clone(), CommitmentConfig::confirmed());
-
-    let feepayer_address = default_signer.pubkey();
-    let feepayer_balance = rpc_client.get_balance(&default_signer.pubkey()).await?;
 
-    let transfer_amount = rand::thread_rng().gen_range(0..(feepayer_balance / 2));
-
-    if verbose {
-        println!(
-            "Fee payer: {}, Amount: {}",
-            feepayer_address,
-            Sol(transfer_amount)
-        );
-        println!("Extra addresses: {extra_addresses:?}");
+    let mut rpc_headers = HeaderMap::new();
+    for header in matches.values_of("header").into_iter().flatten() {
+        let (name, value) = parse_header(header)?;
+        rpc_headers.append(name, value);
+    }
+    if let Some(rpc_token) = matches.value_of("rpc_token") {
+        let mut value = HeaderValue::from_str(&format!("Bearer {rpc_token}"))
+            .map_err(|err| format!("error: invalid RPC token: {err}"))?;
+        value.set_sensitive(true);
+        rpc_headers.insert(AUTHORIZATION, value);
+    }
+    // A User-Agent given with --header wins over the default
+    if matches.occurrences_of("user_agent") > 0 || !rpc_headers.contains_key(USER_AGENT) {
+        let user_agent = matches.value_of("user_agent").unwrap();
+        let value = HeaderValue::from_str(user_agent)
+            .map_err(|err| format!("error: invalid user agent {user_agent:?}: {err}"))?;
+        rpc_headers.insert(USER_AGENT, value);
     }
 
-    let mut transaction = Transaction::new_unsigned(Message::new(
-        &[transfer_with(
-            &feepayer_address,
-            &feepayer_address,
-            transfer_amount,
-            &extra_addresses,
-        )],
-        Some(&feepayer_address),
-    ));
-
-    let blockhash = rpc_client
-        .get_latest_blockhash()
-        .await
-        .map_err(|err| format!("error: unable to get latest blockhash: {err}"))?;
-
-    transaction
-        .try_sign(&vec![default_signer], blockhash)
-        .map_err(|err| format!("error: failed to sign transaction: {err}"))?;
+    match matches.value_of("log") {
+        Some(filter) => solana_logger::setup_with(filter),
+        None => solana_logger::setup_with_default("solana=info"),
+    }
 
-    let signature = rpc_client
-        .send_and_confirm_transaction_with_spinner(&transaction)
-        .await
-        .map_err(|err| format!("error: send transaction: {err}"))?;
+    let verbose = matches.is_present("verbose");
+    let output_format = OutputFormat::from_str(matches.value_of("output").unwrap());
 
-    println!("Signature: {signature}");
+    let context = Context {
+        cli_config,
+        json_rpc_urls,
+        rpc_headers,
+        rpc_limit: value_of::<usize>(&matches, "max_concurrent_rpc")
+            .map(|max_concurrent_rpc| Arc::new(Semaphore::new(max_concurrent_rpc))),
+        output_format,
+        verbose,
+        strict_url,
+    };
 
-    Ok(())
+    match matches.subcommand() {
+        Some(("send", matches)) => send::run(&context, matches, &mut wallet_manager).await,
+        Some(("blockhash", matches)) => blockhash::run(&context, matches).await,
+        Some(("epoch", _)) => epoch::run(&context).await,
+        Some(("doctor", matches)) => doctor::run(&context, matches).await,
+        Some(("watch", matches)) => watch::run(&context, matches, &mut wallet_manager).await,
+        _ => unreachable!(),
+    }
 }
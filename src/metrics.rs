@@ -0,0 +1,133 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Upper bounds of the `--latency-buckets` default, in seconds
+pub const DEFAULT_LATENCY_BUCKETS: &str = "0.05,0.1,0.25,0.5,1,2.5,5,10,30,60";
+
+/// Parses comma-separated bucket upper bounds in seconds, which must be positive and increasing
+pub fn parse_buckets(s: &str) -> Result<Vec<f64>, String> {
+    let buckets = s
+        .split(',')
+        .map(|bucket| {
+            let bucket = bucket.trim();
+            match bucket.parse::<f64>() {
+                Ok(seconds) if seconds.is_finite() && seconds > 0. => Ok(seconds),
+                Ok(_) => Err(format!(
+                    "bucket {bucket} must be a positive number of seconds"
+                )),
+                Err(err) => Err(format!("bucket {bucket}: {err}")),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("buckets must be in increasing order".to_string());
+    }
+    Ok(buckets)
+}
+
+/// Histogram of durations in seconds, with a count per bucket that isn't yet cumulative
+struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    buckets: Vec<f64>,
+    counts: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(name: &'static str, help: &'static str, buckets: &[f64]) -> Self {
+        Self {
+            name,
+            help,
+            buckets: buckets.to_vec(),
+            counts: vec![0; buckets.len()],
+            count: 0,
+            sum: 0.,
+        }
+    }
+
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = self.buckets.iter().position(|bound| seconds <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn write_openmetrics(&self, out: &mut String) {
+        let name = self.name;
+        // Writing to a String can't fail
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let _ = writeln!(out, "# UNIT {name} seconds");
+        let _ = writeln!(out, "# HELP {name} {}", self.help);
+        let mut cumulative = 0;
+        for (bound, count) in self.buckets.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound:?}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {:?}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+/// The `--metrics-file` histograms, fed by the timings of `--measure-latency`
+pub struct LatencyMetrics {
+    blockhash: Histogram,
+    send: Histogram,
+    confirmation: Histogram,
+}
+
+impl LatencyMetrics {
+    pub fn new(buckets: &[f64]) -> Self {
+        Self {
+            blockhash: Histogram::new(
+                "burri_blockhash_fetch_latency_seconds",
+                "Time taken to fetch the transaction's blockhash",
+                buckets,
+            ),
+            send: Histogram::new(
+                "burri_send_latency_seconds",
+                "Time taken for the RPC node to accept the transaction",
+                buckets,
+            ),
+            confirmation: Histogram::new(
+                "burri_confirmation_latency_seconds",
+                "Time from the transaction being accepted to it being confirmed",
+                buckets,
+            ),
+        }
+    }
+
+    /// Records one transaction's timings, which are in milliseconds like those of
+    /// `--measure-latency`
+    pub fn observe(&mut self, blockhash_ms: f64, send_ms: f64, confirmation_ms: f64) {
+        self.blockhash.observe(blockhash_ms / 1000.);
+        self.send.observe(send_ms / 1000.);
+        self.confirmation.observe(confirmation_ms / 1000.);
+    }
+
+    /// Renders the histograms as an OpenMetrics text exposition
+    fn to_openmetrics(&self) -> String {
+        let mut out = String::new();
+        for histogram in [&self.blockhash, &self.send, &self.confirmation] {
+            histogram.write_openmetrics(&mut out);
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+
+    /// Replaces `path` with the current histograms. The file is written alongside and renamed
+    /// into place, so a scraper never reads it half written
+    pub fn write_file(&self, path: &Path) -> std::io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        fs::write(&partial, self.to_openmetrics())?;
+        fs::rename(&partial, path)
+    }
+}
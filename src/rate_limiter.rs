@@ -0,0 +1,112 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket that paces callers to a target rate, allowing up to one second's worth of burst
+/// to catch up after a slow period
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// `rate` is in acquisitions per second
+    pub fn new(rate: f64) -> Self {
+        let capacity = rate.max(1.);
+        Self {
+            rate,
+            capacity,
+            tokens: 1.,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        self.tokens = (self.tokens
+            + now.duration_since(self.last_refill).as_secs_f64() * self.rate)
+            .min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Changes the rate, keeping the tokens gathered at the old rate
+    pub fn set_rate(&mut self, rate: f64) {
+        self.refill();
+        self.rate = rate;
+        self.capacity = rate.max(1.);
+        self.tokens = self.tokens.min(self.capacity);
+    }
+
+    /// Waits until a token is available and takes it
+    pub async fn acquire(&mut self) {
+        self.refill();
+        if self.tokens < 1. {
+            // A small enough rate waits longer than a Duration can hold, which is as good as forever
+            let wait = Duration::try_from_secs_f64((1. - self.tokens) / self.rate)
+                .unwrap_or(Duration::MAX);
+            tokio::time::sleep(wait).await;
+            self.refill();
+        }
+        self.tokens -= 1.;
+    }
+}
+
+/// A rate that changes linearly from `from` to `to` over `duration`, then holds at `to`
+pub struct Ramp {
+    pub from: f64,
+    pub to: f64,
+    pub duration: Duration,
+}
+
+impl Ramp {
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        let progress = (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.);
+        self.from + (self.to - self.from) * progress
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramp_moves_linearly_then_holds() {
+        let ramp = Ramp {
+            from: 10.,
+            to: 20.,
+            duration: Duration::from_secs(10),
+        };
+        assert_eq!(ramp.rate_at(Duration::ZERO), 10.);
+        assert_eq!(ramp.rate_at(Duration::from_secs(5)), 15.);
+        assert_eq!(ramp.rate_at(Duration::from_secs(30)), 20.);
+    }
+
+    #[tokio::test]
+    async fn tiny_rate_waits_rather_than_panicking() {
+        let mut rate_limiter = RateLimiter::new(1e-300);
+        // The first token is there from the start
+        rate_limiter.acquire().await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), rate_limiter.acquire())
+                .await
+                .is_err()
+        );
+
+        rate_limiter.set_rate(1e-300);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), rate_limiter.acquire())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn high_rate_does_not_wait() {
+        let mut rate_limiter = RateLimiter::new(1_000_000.);
+        let start = Instant::now();
+        for _ in 0..100 {
+            rate_limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
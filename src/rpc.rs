@@ -0,0 +1,240 @@
+use {
+    async_trait::async_trait,
+    reqwest::{
+        header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER},
+        StatusCode,
+    },
+    serde::Deserialize,
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+        nonblocking::rpc_client::RpcClient,
+        rpc_client::RpcClientConfig,
+        rpc_custom_error::{
+            NodeUnhealthyErrorData, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+            JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE,
+        },
+        rpc_request::{RpcError, RpcRequest, RpcResponseErrorData},
+        rpc_sender::{RpcSender, RpcTransportStats},
+    },
+    solana_sdk::commitment_config::CommitmentConfig,
+    std::{
+        future::Future,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tokio::sync::Semaphore,
+};
+
+/// JSON RPC over HTTP, like the client's own `HttpSender` but sending extra headers with every
+/// request
+pub struct HeaderSender {
+    client: reqwest::Client,
+    url: String,
+    request_id: AtomicU64,
+    /// Shared by every sender, bounding how many requests are in flight at once
+    rpc_limit: Option<Arc<Semaphore>>,
+}
+
+impl HeaderSender {
+    pub fn new(url: String, headers: HeaderMap, rpc_limit: Option<Arc<Semaphore>>) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .default_headers(headers)
+                .timeout(Duration::from_secs(30))
+                .build()
+                .expect("build rpc client"),
+            url,
+            request_id: AtomicU64::new(0),
+            rpc_limit,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+#[async_trait]
+impl RpcSender for HeaderSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let request_json = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": request.to_string(),
+            "params": params,
+        })
+        .to_string();
+
+        let mut too_many_requests_retries = 5;
+        loop {
+            // The permit is held only for the request itself, not for a 429 backoff
+            let permit = match &self.rpc_limit {
+                Some(rpc_limit) => Some(rpc_limit.acquire().await.expect("semaphore closed")),
+                None => None,
+            };
+            let response = self
+                .client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(request_json.clone())
+                .send()
+                .await?;
+            drop(permit);
+
+            if !response.status().is_success() {
+                if response.status() == StatusCode::TOO_MANY_REQUESTS
+                    && too_many_requests_retries > 0
+                {
+                    let duration = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|retry_after| retry_after.to_str().ok())
+                        .and_then(|retry_after| retry_after.parse::<u64>().ok())
+                        .filter(|retry_after| *retry_after < 120)
+                        .map(Duration::from_secs)
+                        .unwrap_or(Duration::from_millis(500));
+                    too_many_requests_retries -= 1;
+                    tokio::time::sleep(duration).await;
+                    continue;
+                }
+                return Err(response.error_for_status().unwrap_err().into());
+            }
+
+            let mut json = response.json::<serde_json::Value>().await?;
+            if json["error"].is_object() {
+                let error = json["error"].take();
+                return match serde_json::from_value::<RpcErrorObject>(error.clone()) {
+                    Ok(RpcErrorObject { code, message }) => {
+                        let data = match code {
+                            JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => {
+                                serde_json::from_value(error["data"].clone())
+                                    .map(RpcResponseErrorData::SendTransactionPreflightFailure)
+                                    .unwrap_or(RpcResponseErrorData::Empty)
+                            }
+                            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => {
+                                serde_json::from_value::<NodeUnhealthyErrorData>(
+                                    error["data"].clone(),
+                                )
+                                .map(|data| RpcResponseErrorData::NodeUnhealthy {
+                                    num_slots_behind: data.num_slots_behind,
+                                })
+                                .unwrap_or(RpcResponseErrorData::Empty)
+                            }
+                            _ => RpcResponseErrorData::Empty,
+                        };
+                        Err(RpcError::RpcResponseError {
+                            code,
+                            message,
+                            data,
+                        }
+                        .into())
+                    }
+                    Err(err) => Err(RpcError::RpcRequestError(format!(
+                        "Failed to deserialize RPC error response: {error} [{err}]"
+                    ))
+                    .into()),
+                };
+            }
+            return Ok(json["result"].take());
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+/// One or more RPC endpoints for the same cluster. Each call is tried against the endpoints in
+/// order, moving on to the next endpoint when the call fails to reach the current one
+pub struct FailoverRpcClient {
+    endpoints: Vec<(String, RpcClient)>,
+    verbose: bool,
+}
+
+impl FailoverRpcClient {
+    /// `headers` are sent with every request to every endpoint. With `rpc_limit`, a request waits
+    /// for one of its permits
+    pub fn new(
+        urls: &[String],
+        headers: &HeaderMap,
+        rpc_limit: Option<Arc<Semaphore>>,
+        commitment_config: CommitmentConfig,
+        verbose: bool,
+    ) -> Self {
+        assert!(!urls.is_empty());
+        Self {
+            endpoints: urls
+                .iter()
+                .map(|url| {
+                    (
+                        url.clone(),
+                        RpcClient::new_sender(
+                            HeaderSender::new(url.clone(), headers.clone(), rpc_limit.clone()),
+                            RpcClientConfig::with_commitment(commitment_config),
+                        ),
+                    )
+                })
+                .collect(),
+            verbose,
+        }
+    }
+
+    /// Runs `f` against each endpoint in turn until one of them can be reached. `method` names
+    /// the call in verbose output
+    pub async fn call<'a, T, F, Fut>(&'a self, method: &str, f: F) -> ClientResult<T>
+    where
+        F: Fn(&'a RpcClient) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut endpoints = self.endpoints.iter().peekable();
+        loop {
+            let (url, rpc_client) = endpoints.next().unwrap();
+            match f(rpc_client).await {
+                Err(err) if is_connection_error(&err) && endpoints.peek().is_some() => {
+                    if self.verbose {
+                        println!("{method} failed against {url}: {err}");
+                    }
+                }
+                result => {
+                    if self.verbose && self.endpoints.len() > 1 {
+                        println!("{method} served by {url}");
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+/// Returns true if `err` indicates the endpoint couldn't be reached or couldn't serve the request,
+/// rather than the request itself being rejected
+pub fn is_connection_error(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            err.is_connect()
+                || err.is_timeout()
+                || err.status().is_some_and(|status| status.is_server_error())
+        }
+        // The client looks up the node's version before its first request, and reports failing to
+        // reach the node then as a plain string
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(message)) => {
+            message.starts_with("cluster version query failed")
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,84 @@
+use {
+    crate::{memo_instruction, transaction_to_json, transfer_with},
+    clap::Command,
+    solana_sdk::{
+        hash::Hash, message::Message, packet::PACKET_DATA_SIZE, pubkey,
+        signature::keypair_from_seed, signer::Signer, system_program, transaction::Transaction,
+    },
+};
+
+/// The sample transaction, base64-encoded. Signing is deterministic, so a fixed keypair, amount and
+/// blockhash always give these bytes, as `send --sign-only` relies on
+const EXPECTED_TRANSACTION: &str = "AYHEYy8NZr6cWLozWtELAfeEY1fudNayDhKXJtiOrmDhZHelgHWdaxvAgAnHBv72HT2EwJ4JAdNBvovRx0j/SA4BAAQFGX9rI+FshTLGq8g4+s1ep4m+DHaykgM0A5v6iz02jWEAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAVKU1qZKSEGTSTocWDaOHx8NbXdvJK7geQfqEBBBUSNGgmZ7ikvKueEaFphilv2DWnLUa/VgJQDd7SycK5qJWZoJ8i8FGVS5dEDWALUmSd9zDUfZwf+vKQVNZ8fUI84EAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAgEEAAADBAwCAAAA6AMAAAAAAAACAA5idXJyaSBzZWxmdGVzdA==";
+
+pub fn command() -> Command<'static> {
+    Command::new("selftest").about("Build and check a sample transaction without using the network")
+}
+
+/// Builds, signs and round-trips a representative transaction without touching the network,
+/// failing if anything about it is off
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    fn check(condition: bool, what: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if condition {
+            Ok(())
+        } else {
+            Err(format!("error: selftest failed: {what}").into())
+        }
+    }
+
+    let feepayer = keypair_from_seed(&[42; 32])?;
+    let feepayer_address = feepayer.pubkey();
+    let extra_addresses = [
+        pubkey!("2ke6K3igbrDh9Tr6AjaJq4BqbpRG42AjdiUb71cAksAm"),
+        pubkey!("81aaynHWaKQChcp5Dw47dGxpK1fqzG9MGHgk7EJoz4uu"),
+    ];
+
+    let message = Message::new(
+        &[
+            transfer_with(
+                &system_program::id(),
+                &feepayer_address,
+                &feepayer_address,
+                1_000,
+                &extra_addresses,
+            ),
+            memo_instruction("burri selftest"),
+        ],
+        Some(&feepayer_address),
+    );
+    check(message.header.num_required_signatures == 1, "signer count")?;
+    check(
+        message.account_keys.len() == 1 + extra_addresses.len() + 2,
+        "account key count",
+    )?;
+    check(message.instructions.len() == 2, "instruction count")?;
+
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.try_sign(&[&feepayer], Hash::default())?;
+    check(transaction.verify().is_ok(), "signature verification")?;
+
+    let serialized = bincode::serialize(&transaction)?;
+    check(
+        serialized.len() <= PACKET_DATA_SIZE,
+        "serialized size exceeds the packet size",
+    )?;
+    check(
+        bincode::deserialize::<Transaction>(&serialized)? == transaction,
+        "serialization round trip",
+    )?;
+    check(
+        base64::encode(&serialized) == EXPECTED_TRANSACTION,
+        "serialized transaction doesn't match the known bytes",
+    )?;
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&transaction_to_json(&transaction))?
+    );
+    println!(
+        "Serialized size: {} bytes (max {PACKET_DATA_SIZE})",
+        serialized.len()
+    );
+    println!("Selftest passed");
+    Ok(())
+}
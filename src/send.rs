@@ -0,0 +1,5556 @@
+use {
+    crate::{
+        blockhash::read_blockhash_file,
+        check_strict_url, cluster_name, expand_memo_template, explorer_url, instructions_to_json,
+        memo_instruction,
+        metrics::{parse_buckets, LatencyMetrics, DEFAULT_LATENCY_BUCKETS},
+        print_shell_vars,
+        rate_limiter::{Ramp, RateLimiter},
+        rpc::{is_connection_error, FailoverRpcClient},
+        transaction_to_json, transfer_with, Context, OutputFormat, MAX_MEMO_LEN,
+    },
+    clap::{Arg, ArgMatches, Command},
+    futures::StreamExt,
+    rand::{rngs::StdRng, Rng, SeedableRng},
+    reqwest::header::HeaderMap,
+    serde::{Deserialize, Serialize},
+    solana_clap_v3_utils::{
+        input_parsers::{lamports_of_sol, pubkey_of, pubkeys_of, value_of},
+        input_validators::{
+            is_amount, is_hash, is_parsable, is_url_or_moniker, is_valid_pubkey,
+            normalize_to_url_if_moniker,
+        },
+    },
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+        nonblocking::{
+            pubsub_client::{PubsubClient, PubsubClientError},
+            rpc_client::RpcClient,
+        },
+        rpc_config::{
+            RpcSendTransactionConfig, RpcSignatureSubscribeConfig,
+            RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig,
+        },
+        rpc_request::{RpcError, RpcRequest, RpcResponseErrorData},
+        rpc_response::{Response, RpcSignatureResult},
+    },
+    solana_remote_wallet::remote_wallet::RemoteWalletManager,
+    solana_sdk::{
+        account::Account,
+        commitment_config::CommitmentConfig,
+        compute_budget::ComputeBudgetInstruction,
+        hash::{hash, Hash},
+        instruction::AccountMeta,
+        message::Message,
+        native_token::{lamports_to_sol, sol_to_lamports, Sol},
+        packet::PACKET_DATA_SIZE,
+        program_pack::Pack,
+        pubkey::{Pubkey, MAX_SEEDS, MAX_SEED_LEN},
+        signature::{read_keypair_file, Keypair, Signature},
+        signer::{null_signer::NullSigner, Signer},
+        system_program,
+        transaction::{Transaction, TransactionError},
+    },
+    solana_transaction_status::{
+        EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage, UiTransaction,
+        UiTransactionEncoding,
+    },
+    spl_associated_token_account::{
+        get_associated_token_address, instruction::create_associated_token_account,
+    },
+    std::{
+        collections::BTreeMap,
+        fs::{self, File},
+        io::Write,
+        mem,
+        path::Path,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+    url::Url,
+};
+
+/// Time spent in each phase of a send, in milliseconds
+#[derive(Serialize)]
+struct Latency {
+    blockhash_ms: f64,
+    send_ms: f64,
+    confirmation_ms: f64,
+    total_ms: f64,
+}
+
+#[derive(Serialize)]
+struct SendResult {
+    signature: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idempotency_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency: Option<Latency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compute_units: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<TransactionDetails>,
+}
+
+#[derive(Serialize)]
+struct BalanceChange {
+    address: String,
+    pre_lamports: u64,
+    post_lamports: u64,
+}
+
+/// What the cluster says the confirmed transaction did
+#[derive(Serialize)]
+struct TransactionDetails {
+    slot: u64,
+    fee_lamports: u64,
+    balances: Vec<BalanceChange>,
+    instructions: Vec<serde_json::Value>,
+}
+
+impl TransactionDetails {
+    fn new(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Option<Self> {
+        let meta = transaction.transaction.meta.as_ref()?;
+        let message = match &transaction.transaction.transaction {
+            EncodedTransaction::Json(UiTransaction {
+                message: UiMessage::Parsed(message),
+                ..
+            }) => message,
+            _ => return None,
+        };
+
+        Some(Self {
+            slot: transaction.slot,
+            fee_lamports: meta.fee,
+            balances: message
+                .account_keys
+                .iter()
+                .zip(meta.pre_balances.iter().zip(&meta.post_balances))
+                .map(
+                    |(account_key, (pre_lamports, post_lamports))| BalanceChange {
+                        address: account_key.pubkey.clone(),
+                        pre_lamports: *pre_lamports,
+                        post_lamports: *post_lamports,
+                    },
+                )
+                .collect(),
+            instructions: message
+                .instructions
+                .iter()
+                .filter_map(|instruction| serde_json::to_value(instruction).ok())
+                .collect(),
+        })
+    }
+}
+
+/// Checks that the confirmed transaction left the balance of each of `extra_addresses` alone.
+/// Addresses with no account on-chain show a balance of zero before and after
+fn assert_extra_unchanged(
+    signature: &Signature,
+    details: &TransactionDetails,
+    extra_addresses: &[Pubkey],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let changed = details
+        .balances
+        .iter()
+        .filter(|balance| balance.pre_lamports != balance.post_lamports)
+        .filter(|balance| {
+            extra_addresses
+                .iter()
+                .any(|extra_address| extra_address.to_string() == balance.address)
+        })
+        .map(|balance| {
+            format!(
+                "{}: {} -> {}",
+                balance.address,
+                Sol(balance.pre_lamports),
+                Sol(balance.post_lamports)
+            )
+        })
+        .collect::<Vec<_>>();
+    if !changed.is_empty() {
+        return Err(format!(
+            "error: {signature} changed the balance of extra addresses: {}",
+            changed.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// What a send would cost, without sending it
+#[derive(Serialize)]
+struct Estimate {
+    transactions: usize,
+    total_lamports: u64,
+    total_fee_lamports: u64,
+    total_required_lamports: u64,
+    balance_lamports: u64,
+    sufficient: bool,
+}
+
+/// What the confirmed transactions to one recipient cost, for `--cost-basis`
+#[derive(Serialize)]
+struct CostBasis {
+    /// Empty for the total
+    #[serde(skip_serializing_if = "String::is_empty")]
+    recipient: String,
+    transactions: usize,
+    /// In lamports, or in the mint's base units with `--mint`
+    transferred: u64,
+    fee_lamports: u64,
+    /// Lamports that left the fee payer: the fees, plus the amount when sending SOL to someone
+    /// else, plus the rent of any token account created. A transfer to the fee payer itself only
+    /// loses the fee
+    spent_lamports: u64,
+}
+
+impl CostBasis {
+    fn new(recipient: String) -> Self {
+        Self {
+            recipient,
+            transactions: 0,
+            transferred: 0,
+            fee_lamports: 0,
+            spent_lamports: 0,
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.transactions += other.transactions;
+        self.transferred = self.transferred.saturating_add(other.transferred);
+        self.fee_lamports = self.fee_lamports.saturating_add(other.fee_lamports);
+        self.spent_lamports = self.spent_lamports.saturating_add(other.spent_lamports);
+    }
+}
+
+/// What one build of the transaction costs, for `--diff-flag`
+#[derive(Serialize)]
+struct TransactionCost {
+    size: usize,
+    accounts: usize,
+    compute_units: Option<u64>,
+}
+
+/// One transfer listed by `--plan`
+#[derive(Serialize)]
+struct PlannedTransfer {
+    recipient: String,
+    lamports: u64,
+    fee_lamports: u64,
+}
+
+/// One line of `--ndjson` output
+#[derive(Serialize)]
+struct NdjsonRecord<'a> {
+    iteration: usize,
+    status: &'static str,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    result: Option<&'a SendResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee_payer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recipient: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lamports: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memo: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    extra_addresses: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee_lamports: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl<'a> NdjsonRecord<'a> {
+    /// `transfer` and its fee payer are recorded so that the record can be replayed with
+    /// `--replay`
+    fn new(iteration: usize, transfer: Option<&'a Transfer>, fee_payer: Option<&Pubkey>) -> Self {
+        Self {
+            iteration,
+            status: "",
+            result: None,
+            fee_payer: fee_payer.map(|fee_payer| fee_payer.to_string()),
+            recipient: transfer.map(|transfer| transfer.recipient.to_string()),
+            lamports: transfer.map(|transfer| transfer.lamports),
+            memo: transfer.and_then(|transfer| transfer.memo.as_deref()),
+            extra_addresses: transfer
+                .map(|transfer| {
+                    transfer
+                        .extra_addresses
+                        .iter()
+                        .map(|address| address.to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            fee_lamports: None,
+            error: None,
+        }
+    }
+
+    /// Writes the record as a `--summary-csv` row
+    fn write_csv(&self, csv: &mut dyn Write, recipient: &Pubkey) -> std::io::Result<()> {
+        let details = self.result.and_then(|result| result.details.as_ref());
+        let fields = [
+            self.iteration.to_string(),
+            self.result
+                .map(|result| result.signature.clone())
+                .unwrap_or_default(),
+            recipient.to_string(),
+            self.lamports
+                .map(|lamports| lamports.to_string())
+                .unwrap_or_default(),
+            details
+                .map(|details| details.fee_lamports)
+                .or(self.fee_lamports)
+                .map(|fee| fee.to_string())
+                .unwrap_or_default(),
+            details
+                .map(|details| details.slot.to_string())
+                .unwrap_or_default(),
+            self.status.to_string(),
+            self.error.clone().unwrap_or_default(),
+        ];
+        write_csv_row(csv, &fields)
+    }
+}
+
+const SUMMARY_CSV_HEADER: [&str; 8] = [
+    "index",
+    "signature",
+    "recipient",
+    "lamports",
+    "fee",
+    "slot",
+    "status",
+    "error",
+];
+
+/// Writes one CSV line, quoting the fields that contain a comma, quote or line break
+fn write_csv_row<S: AsRef<str>>(csv: &mut dyn Write, fields: &[S]) -> std::io::Result<()> {
+    let line = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(csv, "{line}")?;
+    csv.flush()
+}
+
+/// A line of an earlier run's `--ndjson` output, as read back by `--replay`
+#[derive(Deserialize)]
+struct ReplayRecord {
+    iteration: usize,
+    status: String,
+    fee_payer: Option<String>,
+    recipient: Option<String>,
+    lamports: Option<u64>,
+    memo: Option<String>,
+    #[serde(default)]
+    extra_addresses: Vec<String>,
+}
+
+/// Reads the transfers to send again from the `--ndjson` output at `path`, leaving out the ones
+/// that failed unless `replay_failures` is set. Each must have been paid for by one of
+/// `fee_payers`
+fn read_replay(
+    path: &str,
+    replay_failures: bool,
+    fee_payers: &[Pubkey],
+) -> Result<Vec<Transfer>, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("error: unable to read {path}: {err}"))?;
+    let mut transfers = vec![];
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str::<ReplayRecord>(line)
+            .map_err(|err| format!("error: {path}:{}: {err}", line_number + 1))?;
+        if record.status == "failed" && !replay_failures {
+            continue;
+        }
+        let (fee_payer, recipient, lamports) =
+            match (&record.fee_payer, &record.recipient, record.lamports) {
+                (Some(fee_payer), Some(recipient), Some(lamports)) => {
+                    (fee_payer, recipient, lamports)
+                }
+                _ => {
+                    eprintln!(
+                        "warning: {path}:{}: transaction {} has no recorded transfer, skipping it",
+                        line_number + 1,
+                        record.iteration
+                    );
+                    continue;
+                }
+            };
+        let invalid = |err| format!("error: {path}:{}: {err}", line_number + 1);
+        let fee_payer = fee_payer.parse::<Pubkey>().map_err(invalid)?;
+        let recipient = recipient.parse::<Pubkey>().map_err(invalid)?;
+        let signer = fee_payers
+            .iter()
+            .position(|address| *address == fee_payer)
+            .ok_or_else(|| {
+                format!(
+                    "error: {path}:{}: transaction {} was paid for by {fee_payer}, which isn't a \
+                     signer",
+                    line_number + 1,
+                    record.iteration
+                )
+            })?;
+        let extra_addresses = record
+            .extra_addresses
+            .iter()
+            .map(|address| address.parse::<Pubkey>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        transfers.push(Transfer {
+            signer,
+            recipient,
+            lamports,
+            memo: record.memo,
+            extra_addresses,
+        });
+    }
+    if transfers.is_empty() {
+        return Err(format!("error: {path} has no transactions to replay").into());
+    }
+    Ok(transfers)
+}
+
+/// Totals for a run of more than one transaction
+#[derive(Serialize)]
+struct Summary {
+    transactions: usize,
+    succeeded: usize,
+    failed: usize,
+    skipped: usize,
+    elapsed_secs: f64,
+    tps: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_p50_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_p90_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_p99_ms: Option<f64>,
+    /// With a `--ramp-*` profile, the rate transactions were started at over the run
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rate_buckets: Vec<RateBucket>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_budget: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retries_used: Option<u32>,
+    /// `--spread-over`, to compare with `elapsed_secs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_secs: Option<f64>,
+    /// With `--stop-on-failure`, the transactions left unstarted after the first failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_started: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RateBucket {
+    start_secs: f64,
+    end_secs: f64,
+    tps: f64,
+}
+
+/// Formats the rate buckets as `<start>-<end>s <TPS>` pairs
+fn format_rate_buckets(summary: &Summary) -> String {
+    summary
+        .rate_buckets
+        .iter()
+        .map(|bucket| {
+            format!(
+                "{:.1}-{:.1}s {:.2} TPS",
+                bucket.start_secs, bucket.end_secs, bucket.tps
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Splits `elapsed` into buckets of `width` and works out the rate of the `starts` in each
+fn rate_buckets(starts: &[Duration], elapsed: Duration, width: Duration) -> Vec<RateBucket> {
+    let width = width.as_secs_f64();
+    let elapsed = elapsed.as_secs_f64();
+    let buckets = (elapsed / width).ceil() as usize;
+    (0..buckets)
+        .map(|bucket| {
+            let start_secs = bucket as f64 * width;
+            let end_secs = (start_secs + width).min(elapsed);
+            let started = starts
+                .iter()
+                .filter(|start| (start_secs..end_secs).contains(&start.as_secs_f64()))
+                .count();
+            RateBucket {
+                start_secs,
+                end_secs,
+                tps: started as f64 / (end_secs - start_secs),
+            }
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of `sorted`, which must be in ascending order
+fn percentile(sorted: &[f64], percentile: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((percentile / 100.) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1_000.
+}
+
+/// Where a transaction being waited on stands
+enum ConfirmationStatus {
+    Pending,
+    /// One of the signatures reached the client's commitment, with the transaction's result
+    Confirmed(Signature, Result<(), TransactionError>),
+    /// Its blockhash expired before it was confirmed
+    Expired,
+}
+
+/// The first of `signatures` to have reached the client's commitment, with its transaction's
+/// result
+async fn landed_signature(
+    rpc_client: &FailoverRpcClient,
+    signatures: &[Signature],
+) -> ClientResult<Option<(Signature, Result<(), TransactionError>)>> {
+    if signatures.is_empty() {
+        return Ok(None);
+    }
+    rpc_client
+        .call("getSignatureStatuses", |rpc_client| async move {
+            let statuses = rpc_client.get_signature_statuses(signatures).await?.value;
+            Ok(signatures
+                .iter()
+                .zip(statuses)
+                .find_map(|(signature, status)| {
+                    status
+                        .filter(|status| status.satisfies_commitment(rpc_client.commitment()))
+                        .map(|status| (*signature, status.status))
+                }))
+        })
+        .await
+}
+
+/// Checks once whether any of `signatures`, different attempts at the same transaction, has
+/// reached the client's commitment, or whether `blockhash` of the last of them has expired
+/// without any of them
+async fn poll_confirmation(
+    rpc_client: &FailoverRpcClient,
+    signatures: &[Signature],
+    blockhash: &Hash,
+    last_valid_block_height: Option<u64>,
+) -> ClientResult<ConfirmationStatus> {
+    if let Some((signature, result)) = landed_signature(rpc_client, signatures).await? {
+        return Ok(ConfirmationStatus::Confirmed(signature, result));
+    }
+    let expired = match last_valid_block_height {
+        Some(last_valid_block_height) => {
+            rpc_client
+                .call("getBlockHeight", |rpc_client| rpc_client.get_block_height())
+                .await?
+                > last_valid_block_height
+        }
+        None => {
+            !rpc_client
+                .call("isBlockhashValid", |rpc_client| {
+                    rpc_client.is_blockhash_valid(blockhash, CommitmentConfig::processed())
+                })
+                .await?
+        }
+    };
+    Ok(if expired {
+        ConfirmationStatus::Expired
+    } else {
+        ConfirmationStatus::Pending
+    })
+}
+
+/// Polls until any of `signatures` reaches the client's commitment, returning the one that did,
+/// and gives up once `blockhash`, that of the last signature, expires. Expiry is judged by the
+/// block height passing `last_valid_block_height` when that is known, and by asking the node
+/// whether the blockhash is still valid otherwise. Up to `rpc_retries` queries in a row may fail
+/// before the wait is abandoned
+async fn wait_for_confirmation(
+    rpc_client: &FailoverRpcClient,
+    signatures: &[Signature],
+    blockhash: &Hash,
+    last_valid_block_height: Option<u64>,
+    rpc_retries: u32,
+    verbose: bool,
+) -> ClientResult<Signature> {
+    let signature = signatures.last().expect("no signatures");
+    let mut failed_queries = 0;
+    loop {
+        match poll_confirmation(rpc_client, signatures, blockhash, last_valid_block_height).await {
+            Ok(ConfirmationStatus::Confirmed(signature, result)) => {
+                result?;
+                return Ok(signature);
+            }
+            Ok(ConfirmationStatus::Expired) => {
+                return Err(RpcError::ForUser(format!(
+                    "blockhash expired, transaction dropped: {signature} was not confirmed"
+                ))
+                .into())
+            }
+            Ok(ConfirmationStatus::Pending) => failed_queries = 0,
+            Err(err) if failed_queries < rpc_retries => {
+                failed_queries += 1;
+                if verbose {
+                    println!(
+                        "Confirmation query for {signature} failed ({failed_queries} of \
+                         {rpc_retries} retries): {err}"
+                    );
+                }
+            }
+            // Reported as a request error so it isn't mistaken for the transaction failing
+            Err(err) => {
+                return Err(RpcError::RpcRequestError(format!(
+                    "confirmation query for {signature} failed: {err}"
+                ))
+                .into())
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Polls the status of `signature` until it has at least `min_confirmations` confirmations or is
+/// finalized, failing once `timeout` has elapsed
+async fn wait_for_confirmations(
+    rpc_client: &FailoverRpcClient,
+    signature: &Signature,
+    min_confirmations: usize,
+    timeout: Duration,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signatures = [*signature];
+    let start = Instant::now();
+    loop {
+        let status = rpc_client
+            .call("getSignatureStatuses", |rpc_client| {
+                rpc_client.get_signature_statuses(&signatures)
+            })
+            .await
+            .map_err(|err| format!("error: unable to get status of {signature}: {err}"))?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+
+        // The status reports no confirmation count once the transaction is finalized
+        let confirmations = status.as_ref().map(|status| status.confirmations);
+        match confirmations {
+            Some(None) => {
+                if verbose {
+                    println!("{signature} is finalized");
+                }
+                return Ok(());
+            }
+            Some(Some(confirmations)) if confirmations >= min_confirmations => {
+                if verbose {
+                    println!("{signature} has {confirmations} confirmations");
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        if start.elapsed() >= timeout {
+            return Err(format!(
+                "error: {signature} has {} confirmations, fewer than {min_confirmations} after \
+                 {timeout:?}",
+                confirmations.flatten().unwrap_or_default()
+            )
+            .into());
+        }
+        tokio::time::sleep(Duration::from_millis(400)).await;
+    }
+}
+
+/// The send configuration for `rpc_client`, running preflight at `preflight_commitment` or, by
+/// default, at the client's commitment
+fn send_transaction_config(
+    rpc_client: &RpcClient,
+    preflight_commitment: Option<CommitmentConfig>,
+) -> RpcSendTransactionConfig {
+    RpcSendTransactionConfig {
+        preflight_commitment: Some(
+            preflight_commitment
+                .unwrap_or_else(|| rpc_client.commitment())
+                .commitment,
+        ),
+        ..RpcSendTransactionConfig::default()
+    }
+}
+
+/// How a sent transaction is waited on, from `--confirm-strategy`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConfirmStrategy {
+    /// The client's progress spinner
+    Spinner,
+    /// Polling the signature status, which the `--tx-expiry-aware` and `--confirm-rpc-retries`
+    /// options tune
+    Poll,
+    /// A `signatureSubscribe` notification over the websocket, waiting up to `--confirm-timeout`
+    Ws,
+}
+
+impl ConfirmStrategy {
+    fn from_str(s: &str) -> Self {
+        match s {
+            "poll" => Self::Poll,
+            "ws" => Self::Ws,
+            _ => Self::Spinner,
+        }
+    }
+}
+
+/// Waits for `signature` to be notified over the `--ws-url` websocket. The status is checked
+/// once the subscription is in place, in case the transaction was confirmed before it was
+async fn wait_for_confirmation_ws(
+    config: &SendConfig<'_>,
+    signature: &Signature,
+) -> ClientResult<()> {
+    let ws_error = |err: PubsubClientError| {
+        ClientError::from(RpcError::RpcRequestError(format!(
+            "websocket {}: {err}",
+            config.ws_url
+        )))
+    };
+    let pubsub_client = PubsubClient::new(&config.ws_url).await.map_err(ws_error)?;
+    let (mut notifications, unsubscribe) = pubsub_client
+        .signature_subscribe(
+            signature,
+            Some(RpcSignatureSubscribeConfig {
+                commitment: Some(config.confirm_commitment),
+                enable_received_notification: Some(false),
+            }),
+        )
+        .await
+        .map_err(ws_error)?;
+
+    let status = config
+        .rpc_client
+        .call("getSignatureStatuses", |rpc_client| {
+            rpc_client.get_signature_status(signature)
+        })
+        .await?;
+    let result = match status {
+        Some(result) => result.map_err(Into::into),
+        None => match tokio::time::timeout(config.confirm_timeout, notifications.next()).await {
+            Ok(Some(Response {
+                value: RpcSignatureResult::ProcessedSignature(result),
+                ..
+            })) => match result.err {
+                Some(err) => Err(err.into()),
+                None => Ok(()),
+            },
+            Ok(_) => Err(RpcError::RpcRequestError(format!(
+                "websocket {} closed before {signature} was confirmed",
+                config.ws_url
+            ))
+            .into()),
+            Err(_) => Err(RpcError::ForUser(format!(
+                "confirmation timed out: {signature} was not confirmed within {:?}",
+                config.confirm_timeout
+            ))
+            .into()),
+        },
+    };
+    drop(notifications);
+    unsubscribe().await;
+    result
+}
+
+/// Waits for the sent `transaction` to be confirmed, the way `--confirm-strategy` says.
+/// `signatures` are those of every attempt at sending it, the last being `transaction`'s own, and
+/// whichever of them lands is returned. Polling watches all of them, while the spinner and the
+/// websocket wait for the last and only check the others if it doesn't land
+async fn confirm(
+    config: &SendConfig<'_>,
+    signatures: &[Signature],
+    transaction: &Transaction,
+    last_valid_block_height: Option<u64>,
+) -> ClientResult<Signature> {
+    let (signature, earlier) = signatures.split_last().expect("no signatures");
+    let result = match config.confirm_strategy {
+        ConfirmStrategy::Spinner => {
+            config
+                .rpc_client
+                .call("confirmTransaction", |rpc_client| {
+                    rpc_client.confirm_transaction_with_spinner(
+                        signature,
+                        &transaction.message.recent_blockhash,
+                        rpc_client.commitment(),
+                    )
+                })
+                .await
+        }
+        ConfirmStrategy::Poll => {
+            return wait_for_confirmation(
+                &config.rpc_client,
+                signatures,
+                &transaction.message.recent_blockhash,
+                last_valid_block_height,
+                config.confirm_rpc_retries,
+                config.verbose,
+            )
+            .await
+        }
+        ConfirmStrategy::Ws => wait_for_confirmation_ws(config, signature).await,
+    };
+    match result {
+        Err(err) if !earlier.is_empty() && err.get_transaction_error().is_none() => {
+            match landed_signature(&config.rpc_client, earlier).await? {
+                Some((signature, result)) => result.map(|()| signature).map_err(Into::into),
+                None => Err(err),
+            }
+        }
+        result => result.map(|()| *signature),
+    }
+}
+
+/// Sends `transaction` and waits for it, or any earlier attempt in `submitted`, to be confirmed.
+/// Its signature is added to `submitted`, and the one that landed is returned. With
+/// `--measure-latency`, the time taken to accept the send and to confirm the transaction are
+/// returned alongside the signature
+async fn send_and_confirm(
+    config: &SendConfig<'_>,
+    transaction: &Transaction,
+    last_valid_block_height: Option<u64>,
+    submitted: &mut Vec<Signature>,
+) -> ClientResult<(Signature, Option<(Duration, Duration)>)> {
+    let send_start = Instant::now();
+    let signature = config
+        .rpc_client
+        .call("sendTransaction", |rpc_client| {
+            rpc_client.send_transaction_with_config(
+                transaction,
+                send_transaction_config(rpc_client, config.preflight_commitment),
+            )
+        })
+        .await?;
+    let send_elapsed = send_start.elapsed();
+
+    // The spinner hides the signature until confirmation, which may never come
+    if config.confirm_strategy == ConfirmStrategy::Spinner || config.verbose {
+        eprintln!("Sent {signature}, waiting for confirmation");
+    }
+    submitted.push(signature);
+    let landed = confirm(config, submitted, transaction, last_valid_block_height).await?;
+    if landed != signature {
+        eprintln!("Earlier attempt {landed} landed rather than {signature}");
+    }
+    Ok((
+        landed,
+        config
+            .measure_latency
+            .then(|| (send_elapsed, send_start.elapsed())),
+    ))
+}
+
+/// Simulates `transaction` and fails if it consumes more than `max_compute_units`, as a
+/// transaction built with far too many accounts would
+async fn check_compute_units(
+    rpc_client: &FailoverRpcClient,
+    transaction: &Transaction,
+    max_compute_units: u64,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = rpc_client
+        .call("simulateTransaction", |rpc_client| {
+            rpc_client.simulate_transaction(transaction)
+        })
+        .await
+        .map_err(|err| format!("error: unable to simulate transaction: {err}"))?
+        .value;
+    if let Some(err) = result.err {
+        return Err(format!("error: simulation failed: {err}").into());
+    }
+    let units_consumed = result
+        .units_consumed
+        .ok_or("error: simulation did not report the compute units consumed")?;
+    if units_consumed > max_compute_units {
+        return Err(format!(
+            "error: transaction would consume {units_consumed} compute units, over \
+             --max-compute-units {max_compute_units}, not sending"
+        )
+        .into());
+    }
+    if verbose {
+        println!("Simulated transaction consumes {units_consumed} compute units");
+    }
+    Ok(())
+}
+
+/// How one endpoint handled the transaction sent by `--compare-url`
+#[derive(Serialize)]
+struct EndpointComparison {
+    url: String,
+    accepted_ms: Option<f64>,
+    /// The endpoint had already seen the transaction, through the other endpoint
+    already_processed: bool,
+    confirmed_ms: Option<f64>,
+    error: Option<String>,
+}
+
+/// Sends `transaction` to one endpoint and waits for it to be confirmed there, timing both from
+/// `start`
+async fn send_to_endpoint(
+    config: &SendConfig<'_>,
+    rpc_client: &FailoverRpcClient,
+    url: &str,
+    transaction: &Transaction,
+    last_valid_block_height: Option<u64>,
+    start: Instant,
+) -> EndpointComparison {
+    let mut comparison = EndpointComparison {
+        url: url.to_string(),
+        accepted_ms: None,
+        already_processed: false,
+        confirmed_ms: None,
+        error: None,
+    };
+    match rpc_client
+        .call("sendTransaction", |rpc_client| {
+            rpc_client.send_transaction_with_config(
+                transaction,
+                send_transaction_config(rpc_client, config.preflight_commitment),
+            )
+        })
+        .await
+    {
+        Ok(_) => comparison.accepted_ms = Some(duration_ms(start.elapsed())),
+        Err(err)
+            if err.kind().get_transaction_error() == Some(TransactionError::AlreadyProcessed) =>
+        {
+            comparison.accepted_ms = Some(duration_ms(start.elapsed()));
+            comparison.already_processed = true;
+        }
+        Err(err) => {
+            comparison.error = Some(err.to_string());
+            return comparison;
+        }
+    }
+    match wait_for_confirmation(
+        rpc_client,
+        &transaction.signatures[..1],
+        &transaction.message.recent_blockhash,
+        last_valid_block_height,
+        config.confirm_rpc_retries,
+        config.verbose,
+    )
+    .await
+    {
+        Ok(_) => comparison.confirmed_ms = Some(duration_ms(start.elapsed())),
+        Err(err) => comparison.error = Some(err.to_string()),
+    }
+    comparison
+}
+
+/// Sends `transaction` to the primary endpoint and the `--compare-url` endpoint at the same time
+/// and reports how each of them handled it
+async fn compare_endpoints(
+    config: &SendConfig<'_>,
+    transaction: &Transaction,
+    last_valid_block_height: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (compare_url, compare_rpc_client) = config.compare.as_ref().unwrap();
+    let start = Instant::now();
+    let (primary, compare) = tokio::join!(
+        send_to_endpoint(
+            config,
+            &config.rpc_client,
+            config.json_rpc_url,
+            transaction,
+            last_valid_block_height,
+            start,
+        ),
+        send_to_endpoint(
+            config,
+            compare_rpc_client,
+            compare_url,
+            transaction,
+            last_valid_block_height,
+            start,
+        ),
+    );
+    let signature = transaction.signatures[0].to_string();
+    // Both endpoints should agree on whether the transaction was confirmed
+    let agree = primary.confirmed_ms.is_some() == compare.confirmed_ms.is_some();
+
+    match config.output_format {
+        OutputFormat::Text => {
+            println!("Signature: {signature}");
+            for comparison in [&primary, &compare] {
+                let accepted = match comparison.accepted_ms {
+                    Some(_) if comparison.already_processed => "already processed".to_string(),
+                    Some(accepted_ms) => format!("accepted in {accepted_ms:.1}ms"),
+                    None => "not accepted".to_string(),
+                };
+                let confirmed = match comparison.confirmed_ms {
+                    Some(confirmed_ms) => format!("confirmed in {confirmed_ms:.1}ms"),
+                    None => "not confirmed".to_string(),
+                };
+                println!("{}: {accepted}, {confirmed}", comparison.url);
+                if let Some(error) = &comparison.error {
+                    println!("  {error}");
+                }
+            }
+            println!("Endpoints agree: {}", if agree { "yes" } else { "no" });
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "signature": signature,
+                "endpoints": [primary, compare],
+                "agree": agree,
+            })
+        ),
+        OutputFormat::Shell => {
+            let mut vars = vec![("SIGNATURE", signature)];
+            for (comparison, accepted_name, confirmed_name) in [
+                (&primary, "PRIMARY_ACCEPTED_MS", "PRIMARY_CONFIRMED_MS"),
+                (&compare, "COMPARE_ACCEPTED_MS", "COMPARE_CONFIRMED_MS"),
+            ] {
+                if let Some(accepted_ms) = comparison.accepted_ms {
+                    vars.push((accepted_name, format!("{accepted_ms:.1}")));
+                }
+                if let Some(confirmed_ms) = comparison.confirmed_ms {
+                    vars.push((confirmed_name, format!("{confirmed_ms:.1}")));
+                }
+            }
+            vars.push(("AGREE", agree.to_string()));
+            print_shell_vars(&vars);
+        }
+    }
+
+    if primary.confirmed_ms.is_none() {
+        return Err(format!(
+            "error: transaction was not confirmed by {}",
+            config.json_rpc_url
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns true if `err` is likely to go away if the send is retried, and false for errors that
+/// will fail the same way every time, like insufficient funds or a bad signature
+fn is_transient_error(err: &ClientError) -> bool {
+    if let Some(transaction_error) = err.kind().get_transaction_error() {
+        return matches!(transaction_error, TransactionError::BlockhashNotFound);
+    }
+    if is_connection_error(err) {
+        return true;
+    }
+    match err.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::NodeUnhealthy { .. },
+            ..
+        }) => true,
+        // Confirmation timed out before the blockhash expired
+        ClientErrorKind::RpcError(RpcError::ForUser(_)) => true,
+        _ => false,
+    }
+}
+
+/// Totals the compute units reported by the top-level "Program <id> consumed <n> of <m> compute
+/// units" log lines. Native programs, like the system program, don't log their consumption
+fn compute_units_from_logs(logs: &[String]) -> Option<u64> {
+    let mut depth = 0;
+    let mut compute_units = None;
+    for log in logs {
+        let log = match log.strip_prefix("Program ") {
+            Some(log) => log,
+            None => continue,
+        };
+        if log.contains(" invoke [") {
+            depth += 1;
+        } else if log.ends_with(" success") || log.contains(" failed: ") {
+            depth -= 1;
+        } else if depth == 1 {
+            if let Some(consumed) = log
+                .split_once(" consumed ")
+                .and_then(|(_, consumed)| consumed.split_once(" of "))
+                .and_then(|(consumed, _)| consumed.parse::<u64>().ok())
+            {
+                *compute_units.get_or_insert(0) += consumed;
+            }
+        }
+    }
+    compute_units
+}
+
+/// Fetches the confirmed transaction, retrying briefly since the node may not have its metadata
+/// available immediately after confirmation
+async fn get_confirmed_transaction(
+    rpc_client: &FailoverRpcClient,
+    signature: &Signature,
+) -> Option<EncodedConfirmedTransactionWithStatusMeta> {
+    for attempt in 0..3 {
+        if attempt > 0 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        if let Ok(transaction) = rpc_client
+            .call("getTransaction", |rpc_client| {
+                rpc_client.get_transaction(signature, UiTransactionEncoding::JsonParsed)
+            })
+            .await
+        {
+            if transaction.transaction.meta.is_some() {
+                return Some(transaction);
+            }
+        }
+    }
+    None
+}
+
+/// How long `--extra-from-program` waits for the program's accounts, across all endpoints
+const PROGRAM_ACCOUNTS_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct RpcKeyedAddress {
+    pubkey: String,
+}
+
+/// Returns the addresses of up to `limit` accounts owned by `program_id`, only counting accounts
+/// of `data_size` bytes if given. The accounts' data is sliced away, so the response carries
+/// little more than their addresses
+async fn get_program_addresses(
+    rpc_client: &FailoverRpcClient,
+    program_id: &Pubkey,
+    data_size: Option<u64>,
+    limit: usize,
+    verbose: bool,
+) -> Result<Vec<Pubkey>, Box<dyn std::error::Error>> {
+    let mut config = serde_json::json!({
+        "encoding": "base64",
+        "dataSlice": {"offset": 0, "length": 0},
+    });
+    if let Some(data_size) = data_size {
+        config["filters"] = serde_json::json!([{ "dataSize": data_size }]);
+    }
+    let accounts = tokio::time::timeout(
+        PROGRAM_ACCOUNTS_TIMEOUT,
+        rpc_client.call("getProgramAccounts", |rpc_client| {
+            rpc_client.send::<Vec<RpcKeyedAddress>>(
+                RpcRequest::GetProgramAccounts,
+                serde_json::json!([program_id.to_string(), config]),
+            )
+        }),
+    )
+    .await
+    .map_err(|_| {
+        format!(
+            "error: timed out after {PROGRAM_ACCOUNTS_TIMEOUT:?} getting the accounts of \
+             {program_id}"
+        )
+    })?
+    .map_err(|err| format!("error: unable to get the accounts of {program_id}: {err}"))?;
+
+    let mut addresses = accounts
+        .into_iter()
+        .map(|account| account.pubkey.parse::<Pubkey>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if verbose {
+        match data_size {
+            Some(data_size) => println!(
+                "{} accounts of {data_size} bytes owned by {program_id}, taking up to {limit}",
+                addresses.len()
+            ),
+            None => println!(
+                "{} accounts owned by {program_id}, taking up to {limit}",
+                addresses.len()
+            ),
+        }
+    }
+    // The node returns them in no particular order
+    addresses.sort_unstable();
+    addresses.truncate(limit);
+    Ok(addresses)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcPrioritizationFee {
+    prioritization_fee: u64,
+}
+
+/// Returns the median of the recent prioritization fees, in micro-lamports per compute unit, paid
+/// by transactions that locked `addresses`
+async fn get_median_prioritization_fee(
+    rpc_client: &FailoverRpcClient,
+    addresses: &[Pubkey],
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let addresses = addresses
+        .iter()
+        .map(|address| address.to_string())
+        .collect::<Vec<_>>();
+    let mut fees = rpc_client
+        .call("getRecentPrioritizationFees", |rpc_client| {
+            rpc_client.send::<Vec<RpcPrioritizationFee>>(
+                RpcRequest::Custom {
+                    method: "getRecentPrioritizationFees",
+                },
+                serde_json::json!([addresses]),
+            )
+        })
+        .await?
+        .into_iter()
+        .map(|fee| fee.prioritization_fee)
+        .collect::<Vec<_>>();
+
+    fees.sort_unstable();
+    Ok(fees.get(fees.len() / 2).copied().unwrap_or_default())
+}
+
+/// Writes the signed `transaction` and the `error` it failed with to a new file in `dir`, named
+/// after the time and the transaction's signature. The transaction can be resent as is with
+/// `--raw-transaction`
+fn dump_failed_transaction(
+    dir: &Path,
+    transaction: &Transaction,
+    error: &str,
+) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = dir.join(format!(
+        "failed-{timestamp}-{}.json",
+        transaction.signatures[0]
+    ));
+    let dump = serde_json::json!({
+        "signature": transaction.signatures[0].to_string(),
+        "transaction": base64::encode(bincode::serialize(transaction)?),
+        "error": error,
+    });
+    fs::write(&path, serde_json::to_string_pretty(&dump)?)?;
+    Ok(path)
+}
+
+/// Submits a base64-encoded signed transaction exactly as given
+async fn send_raw_transaction(
+    rpc_client: &FailoverRpcClient,
+    raw_transaction: &str,
+    output_format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = base64::decode(raw_transaction)
+        .map_err(|err| format!("error: invalid base64 transaction: {err}"))?;
+    let transaction = bincode::deserialize::<Transaction>(&bytes)
+        .map_err(|err| format!("error: unable to deserialize transaction: {err}"))?;
+    if !transaction.is_signed() {
+        return Err("error: transaction is not fully signed".into());
+    }
+    transaction
+        .verify()
+        .map_err(|err| format!("error: transaction signature verification failed: {err}"))?;
+
+    let signature = rpc_client
+        .call("sendTransaction", |rpc_client| {
+            rpc_client.send::<String>(
+                RpcRequest::SendTransaction,
+                serde_json::json!([raw_transaction, {"encoding": "base64"}]),
+            )
+        })
+        .await
+        .map_err(|err| format!("error: send transaction: {err}"))?;
+
+    match output_format {
+        OutputFormat::Text => println!("Signature: {signature}"),
+        OutputFormat::Json => println!("{}", serde_json::json!({ "signature": signature })),
+        OutputFormat::Shell => print_shell_vars(&[("SIGNATURE", signature)]),
+    }
+    Ok(())
+}
+
+/// The account `--assert-effect` expects a transfer to credit, and by how much
+struct Credit {
+    address: Pubkey,
+    /// Whether `address` is a token account, credited in tokens rather than lamports
+    token: bool,
+    amount: u64,
+}
+
+/// The balance `account` holds, in tokens if it is a token account. A missing account holds
+/// nothing
+fn balance_of(account: Option<&Account>, token: bool) -> Result<u64, Box<dyn std::error::Error>> {
+    Ok(match account {
+        None => 0,
+        Some(account) if token => spl_token::state::Account::unpack(&account.data)?.amount,
+        Some(account) => account.lamports,
+    })
+}
+
+/// Simulates `transaction` and checks that it changes the fee payer's balance by exactly the fee
+/// plus the `transferred` lamports, and credits the `credit` account with exactly its amount. A
+/// transfer from the fee payer back to itself nets out, so `transferred` is zero and there is no
+/// separate account to credit then
+async fn assert_effect(
+    rpc_client: &FailoverRpcClient,
+    transaction: &Transaction,
+    feepayer_address: &Pubkey,
+    transferred: u64,
+    credit: Option<Credit>,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fee = rpc_client
+        .call("getFeeForMessage", |rpc_client| {
+            rpc_client.get_fee_for_message(&transaction.message)
+        })
+        .await
+        .map_err(|err| format!("error: unable to get fee: {err}"))?;
+    let mut addresses = vec![*feepayer_address];
+    addresses.extend(credit.as_ref().map(|credit| credit.address));
+    let pre_accounts = rpc_client
+        .call("getMultipleAccounts", |rpc_client| {
+            rpc_client
+                .get_multiple_accounts_with_commitment(&addresses, CommitmentConfig::confirmed())
+        })
+        .await?
+        .value;
+
+    let result = rpc_client
+        .call("simulateTransaction", |rpc_client| {
+            rpc_client.simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: true,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: None,
+                        addresses: addresses
+                            .iter()
+                            .map(|address| address.to_string())
+                            .collect(),
+                    }),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+        })
+        .await
+        .map_err(|err| format!("error: unable to simulate transaction: {err}"))?
+        .value;
+    if let Some(err) = result.err {
+        return Err(format!("error: simulation failed: {err}").into());
+    }
+    let post_accounts = result
+        .accounts
+        .filter(|accounts| accounts.len() == addresses.len())
+        .ok_or("error: simulation did not return the accounts")?;
+
+    let pre_balance = balance_of(pre_accounts[0].as_ref(), false)?;
+    let post_balance = post_accounts[0]
+        .as_ref()
+        .map(|account| account.lamports)
+        .ok_or("error: simulation did not return the fee payer account")?;
+    let change = post_balance as i128 - pre_balance as i128;
+    let expected_change = -(fee as i128 + transferred as i128);
+    if change != expected_change {
+        return Err(format!(
+            "error: simulated effect doesn't match, not sending: fee payer balance changed by \
+             {change} lamports, expected {expected_change}"
+        )
+        .into());
+    }
+    if verbose {
+        println!("Simulated effect matches: fee payer balance changed by {change} lamports");
+    }
+
+    if let Some(credit) = credit {
+        let unit = if credit.token { "tokens" } else { "lamports" };
+        let pre_balance = balance_of(pre_accounts[1].as_ref(), credit.token)?;
+        let post_account = post_accounts[1]
+            .as_ref()
+            .map(|account| {
+                account
+                    .decode::<Account>()
+                    .ok_or("error: unable to decode the simulated recipient account")
+            })
+            .transpose()?;
+        let post_balance = balance_of(post_account.as_ref(), credit.token)?;
+        let change = post_balance as i128 - pre_balance as i128;
+        if change != credit.amount as i128 {
+            return Err(format!(
+                "error: simulated effect doesn't match, not sending: {} balance changed by \
+                 {change} {unit}, expected {}",
+                credit.address, credit.amount
+            )
+            .into());
+        }
+        if verbose {
+            println!(
+                "Simulated effect matches: {} balance changed by {change} {unit}",
+                credit.address
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Polls the balance of `address` until it reaches `min_balance` lamports, failing once `timeout`
+/// has elapsed
+async fn wait_for_balance(
+    rpc_client: &FailoverRpcClient,
+    address: &Pubkey,
+    min_balance: u64,
+    timeout: Duration,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    loop {
+        let balance = rpc_client
+            .call("getBalance", |rpc_client| rpc_client.get_balance(address))
+            .await?;
+        if balance >= min_balance {
+            if verbose {
+                println!(
+                    "Balance of {address} reached {} after {:?}",
+                    Sol(balance),
+                    start.elapsed()
+                );
+            }
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(format!(
+                "error: balance of {address} is {}, still below {} after {timeout:?}",
+                Sol(balance),
+                Sol(min_balance)
+            )
+            .into());
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Genesis hash of mainnet-beta, where there is no faucet to airdrop from
+const MAINNET_BETA_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+/// Airdrops `lamports` to `address` from the RPC node's faucet, falling back to the web faucet at
+/// `faucet_url` if that fails, and waits for the balance to reflect it
+async fn airdrop(
+    rpc_client: &FailoverRpcClient,
+    address: &Pubkey,
+    lamports: u64,
+    faucet_url: Option<&str>,
+    timeout: Duration,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let genesis_hash = rpc_client
+        .call("getGenesisHash", |rpc_client| rpc_client.get_genesis_hash())
+        .await
+        .map_err(|err| format!("error: unable to get genesis hash: {err}"))?;
+    if genesis_hash.to_string() == MAINNET_BETA_GENESIS_HASH {
+        return Err("error: --airdrop is not available on mainnet-beta".into());
+    }
+
+    let balance = rpc_client
+        .call("getBalance", |rpc_client| rpc_client.get_balance(address))
+        .await?;
+
+    let funded_by = match rpc_client
+        .call("requestAirdrop", |rpc_client| {
+            rpc_client.request_airdrop(address, lamports)
+        })
+        .await
+    {
+        Ok(signature) => {
+            if verbose {
+                println!("Airdrop signature: {signature}");
+            }
+            "the RPC node".to_string()
+        }
+        Err(err) => {
+            let faucet_url = faucet_url.ok_or_else(|| format!("error: airdrop failed: {err}"))?;
+            eprintln!("warning: airdrop failed, trying {faucet_url}: {err}");
+            let response = reqwest::Client::new()
+                .post(faucet_url)
+                .json(&serde_json::json!({
+                    "address": address.to_string(),
+                    "lamports": lamports,
+                }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|err| format!("error: faucet request failed: {err}"))?;
+            if verbose {
+                println!(
+                    "Faucet response: {}",
+                    response.text().await.unwrap_or_default()
+                );
+            }
+            faucet_url.to_string()
+        }
+    };
+
+    wait_for_balance(
+        rpc_client,
+        address,
+        balance.saturating_add(lamports),
+        timeout,
+        verbose,
+    )
+    .await?;
+    eprintln!("Airdropped {} to {address} from {funded_by}", Sol(lamports));
+    Ok(())
+}
+
+/// Opens `path` for `--result-socket`, connecting to it if it's a Unix domain socket and opening it
+/// for writing otherwise, as with a named pipe
+fn open_result_socket(path: &str) -> std::io::Result<Box<dyn Write>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if std::fs::metadata(path)?.file_type().is_socket() {
+            return Ok(Box::new(std::os::unix::net::UnixStream::connect(path)?));
+        }
+    }
+    Ok(Box::new(
+        std::fs::OpenOptions::new().append(true).open(path)?,
+    ))
+}
+
+/// Reads a JSON array of keypairs, each in the byte array form of a keypair file
+fn read_keypairs_file(path: &str) -> Result<Vec<Keypair>, Box<dyn std::error::Error>> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("error: unable to read {path}: {err}"))?;
+    let keypairs = serde_json::from_str::<Vec<Vec<u8>>>(&contents)
+        .map_err(|err| format!("error: unable to parse {path}: {err}"))?;
+    keypairs
+        .iter()
+        .enumerate()
+        .map(|(index, bytes)| {
+            if bytes.len() != 64 {
+                return Err(format!(
+                    "error: keypair {index} in {path} is {} bytes, expected 64",
+                    bytes.len()
+                )
+                .into());
+            }
+            Keypair::from_bytes(bytes)
+                .map_err(|err| format!("error: keypair {index} in {path} is invalid: {err}").into())
+        })
+        .collect()
+}
+
+/// Checks every signature of `transaction` against its message and signer
+fn verify_signatures(transaction: &Transaction) -> Result<(), String> {
+    transaction
+        .verify()
+        .map_err(|err| format!("error: transaction failed local signature verification: {err}"))
+}
+
+/// The fee payer `signer` and whichever `cosigners` `message` also requires
+fn required_signers<'b>(
+    message: &Message,
+    signer: &'b dyn Signer,
+    cosigners: &'b [Keypair],
+) -> Vec<&'b dyn Signer> {
+    let required = &message.account_keys[..message.header.num_required_signatures as usize];
+    let mut signers = vec![signer];
+    for cosigner in cosigners {
+        let pubkey = cosigner.pubkey();
+        if pubkey != signer.pubkey() && required.contains(&pubkey) {
+            signers.push(cosigner);
+        }
+    }
+    signers
+}
+
+/// Test-signs a zero-lamport transfer to itself with each of `signers`, so a missing hardware
+/// wallet or a rejected prompt shows up before any transaction is built. The message is a real
+/// one because a hardware wallet won't sign arbitrary bytes
+fn check_signers(signers: &[&dyn Signer], verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut unavailable = vec![];
+    for signer in signers {
+        let pubkey = signer.pubkey();
+        let message = Message::new(
+            &[transfer_with(
+                &system_program::id(),
+                &pubkey,
+                &pubkey,
+                0,
+                &[],
+            )],
+            Some(&pubkey),
+        )
+        .serialize();
+        match signer.try_sign_message(&message) {
+            Ok(signature) if signature.verify(pubkey.as_ref(), &message) => {
+                if verbose {
+                    println!("Signer {pubkey} can sign");
+                }
+            }
+            Ok(_) => unavailable.push(format!("{pubkey} (signature doesn't verify)")),
+            Err(err) => unavailable.push(format!("{pubkey} ({err})")),
+        }
+    }
+    if !unavailable.is_empty() {
+        return Err(format!(
+            "error: {} of {} signers can't sign: {}",
+            unavailable.len(),
+            signers.len(),
+            unavailable.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Loads the `*.json` keypairs in `keypair_dir`, in file name order, along with their paths
+fn read_keypair_dir(
+    keypair_dir: &str,
+) -> Result<Vec<(Keypair, String)>, Box<dyn std::error::Error>> {
+    let mut paths = std::fs::read_dir(keypair_dir)
+        .map_err(|err| format!("error: unable to read {keypair_dir}: {err}"))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(format!("error: no *.json keypairs in {keypair_dir}").into());
+    }
+
+    paths
+        .iter()
+        .map(|path| {
+            let keypair = read_keypair_file(path).map_err(|err| {
+                format!("error: unable to read keypair {}: {err}", path.display())
+            })?;
+            Ok((keypair, path.display().to_string()))
+        })
+        .collect()
+}
+
+fn is_positive_number(s: &str) -> Result<(), String> {
+    match s.parse::<f64>() {
+        Ok(tps) if tps > 0. => Ok(()),
+        Ok(_) => Err("must be greater than zero".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Finds the program derived address of `program_id` for `seeds`, and its bump seed
+fn find_pda(program_id: &Pubkey, seeds: &[Vec<u8>]) -> Result<(Pubkey, u8), String> {
+    // The bump seed takes up one of the seeds
+    if seeds.len() >= MAX_SEEDS {
+        return Err(format!(
+            "error: {} seeds given, at most {} fit alongside the bump seed",
+            seeds.len(),
+            MAX_SEEDS - 1
+        ));
+    }
+    if let Some(seed) = seeds.iter().find(|seed| seed.len() > MAX_SEED_LEN) {
+        return Err(format!(
+            "error: a seed is {} bytes, the maximum is {MAX_SEED_LEN}",
+            seed.len()
+        ));
+    }
+    let seeds = seeds.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    Ok(Pubkey::find_program_address(&seeds, program_id))
+}
+
+/// Parses a `PUBKEY:SIGNATURE` pair, both base58
+fn parse_pubkey_signature(s: &str) -> Result<(Pubkey, Signature), String> {
+    let (pubkey, signature) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid signature {s:?}, expected PUBKEY:SIGNATURE"))?;
+    let pubkey = pubkey
+        .parse::<Pubkey>()
+        .map_err(|err| format!("invalid address {pubkey}: {err}"))?;
+    let signature = signature
+        .parse::<Signature>()
+        .map_err(|err| format!("invalid signature for {pubkey}: {err}"))?;
+    Ok((pubkey, signature))
+}
+
+/// Sets the blockhash of `transaction` and attaches externally produced signatures in place of
+/// signing it, checking each against the message. Every signer of the transaction must be covered
+fn attach_signatures(
+    transaction: &mut Transaction,
+    signatures: &[(Pubkey, Signature)],
+    blockhash: Hash,
+) -> Result<(), String> {
+    transaction.message.recent_blockhash = blockhash;
+    let message_data = transaction.message_data();
+    let signers = &transaction.message.account_keys
+        [..transaction.message.header.num_required_signatures as usize];
+
+    for (pubkey, signature) in signatures {
+        let position = signers
+            .iter()
+            .position(|signer| signer == pubkey)
+            .ok_or_else(|| format!("error: {pubkey} is not a signer of the transaction"))?;
+        if !signature.verify(pubkey.as_ref(), &message_data) {
+            return Err(format!(
+                "error: signature for {pubkey} doesn't verify against the message"
+            ));
+        }
+        transaction.signatures[position] = *signature;
+    }
+
+    for (signer, signature) in signers.iter().zip(&transaction.signatures) {
+        if *signature == Signature::default() {
+            return Err(format!("error: missing signature for {signer}"));
+        }
+    }
+    Ok(())
+}
+
+/// Prints the compiled message's account keys in order, with their flags and the instructions
+/// each is the program of, for verbose output
+fn print_account_keys(message: &Message) {
+    let flag = |is_set| if is_set { "yes" } else { "no" };
+    println!("Account keys:");
+    println!(
+        "  {:>3}  {:<6}  {:<8}  {:<44}  Program of",
+        "#", "Signer", "Writable", "Address"
+    );
+    for (index, account_key) in message.account_keys.iter().enumerate() {
+        let program_of = message
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| instruction.program_id_index as usize == index)
+            .map(|(instruction_index, _)| format!("instruction {instruction_index}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let line = format!(
+            "  {index:>3}  {:<6}  {:<8}  {:<44}  {program_of}",
+            flag(message.is_signer(index)),
+            flag(message.is_writable(index)),
+            account_key.to_string()
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Prints the signed `transaction` for `--sign-only`, base64-encoded as `--raw-transaction` takes
+/// it
+fn print_signed_transaction(
+    transaction: &Transaction,
+    output_format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signature = transaction.signatures[0].to_string();
+    let transaction_base64 = base64::encode(bincode::serialize(transaction)?);
+    match output_format {
+        OutputFormat::Text => {
+            println!("Signature: {signature}");
+            println!("Transaction: {transaction_base64}");
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "signature": signature,
+                "transaction": transaction_base64,
+            })
+        ),
+        OutputFormat::Shell => print_shell_vars(&[
+            ("SIGNATURE", signature),
+            ("TRANSACTION", transaction_base64),
+        ]),
+    }
+    Ok(())
+}
+
+/// Prints `message` serialized, which is exactly the bytes each signer signs, along with the
+/// SHA-256 hash of those bytes and the addresses that need to sign
+fn print_message(
+    message: &Message,
+    lamports: u64,
+    output_format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message_bytes = message.serialize();
+    let message_base64 = base64::encode(&message_bytes);
+    let message_sha256 = hash(&message_bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    let signers = &message.account_keys[..message.header.num_required_signatures as usize];
+
+    match output_format {
+        OutputFormat::Text => {
+            println!("Message: {message_base64}");
+            println!("Message SHA-256: {message_sha256}");
+            println!("Blockhash: {}", message.recent_blockhash);
+            println!("Amount: {}", Sol(lamports));
+            for (index, signer) in signers.iter().enumerate() {
+                println!("Signer {index}: {signer}");
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "message": message_base64,
+                "message_sha256": message_sha256,
+                "blockhash": message.recent_blockhash.to_string(),
+                "lamports": lamports,
+                "signers": signers.iter().map(|signer| signer.to_string()).collect::<Vec<_>>(),
+            })
+        ),
+        OutputFormat::Shell => print_shell_vars(&[
+            ("MESSAGE", message_base64),
+            ("MESSAGE_SHA256", message_sha256),
+            ("BLOCKHASH", message.recent_blockhash.to_string()),
+            ("LAMPORTS", lamports.to_string()),
+            (
+                "SIGNERS",
+                signers
+                    .iter()
+                    .map(|signer| signer.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        ]),
+    }
+    Ok(())
+}
+
+/// The SPL Token mint to transfer with `--mint`
+struct TokenMint {
+    address: Pubkey,
+    decimals: u8,
+}
+
+/// Builds the transaction for `transfer` with and without the `--diff-flag` option, simulates
+/// both and prints how they differ. Nothing is sent
+async fn diff_transfer(
+    config: &SendConfig<'_>,
+    diff_flag: &str,
+    transfer: Transfer,
+    rng: &mut StdRng,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut toggled = transfer.clone();
+    let (in_use, what) = match diff_flag {
+        "extra-addresses" => (
+            !mem::take(&mut toggled.extra_addresses).is_empty(),
+            "extra addresses",
+        ),
+        _ => (toggled.memo.take().is_some(), "memo"),
+    };
+    if !in_use {
+        return Err(format!("error: there is no {what} for --diff-flag to leave out").into());
+    }
+
+    let mut costs = vec![];
+    for transfer in [&transfer, &toggled] {
+        let transaction = match send_one(config, transfer, rng, false).await? {
+            Outcome::Built(transaction) => transaction,
+            _ => unreachable!(),
+        };
+        let result = config
+            .rpc_client
+            .call("simulateTransaction", |rpc_client| {
+                rpc_client.simulate_transaction(&transaction)
+            })
+            .await
+            .map_err(|err| format!("error: unable to simulate transaction: {err}"))?
+            .value;
+        if let Some(err) = result.err {
+            eprintln!("warning: simulation failed: {err}");
+        }
+        costs.push(TransactionCost {
+            size: bincode::serialized_size(&transaction)? as usize,
+            accounts: transaction.message.account_keys.len(),
+            compute_units: result.units_consumed,
+        });
+    }
+    let (with, without) = (&costs[0], &costs[1]);
+    let change = |with: usize, without: usize| without as i64 - with as i64;
+
+    match config.output_format {
+        OutputFormat::Text => {
+            let compute_units = |cost: &TransactionCost| {
+                cost.compute_units
+                    .map_or_else(|| "-".to_string(), |units| units.to_string())
+            };
+            println!("Without {diff_flag}:");
+            println!(
+                "{:<14}  {:>10}  {:>10}  {:>10}",
+                "", "With", "Without", "Change"
+            );
+            println!(
+                "{:<14}  {:>10}  {:>10}  {:>+10}",
+                "Size (bytes)",
+                with.size,
+                without.size,
+                change(with.size, without.size)
+            );
+            println!(
+                "{:<14}  {:>10}  {:>10}  {:>+10}",
+                "Accounts",
+                with.accounts,
+                without.accounts,
+                change(with.accounts, without.accounts)
+            );
+            println!(
+                "{:<14}  {:>10}  {:>10}  {:>10}",
+                "Compute units",
+                compute_units(with),
+                compute_units(without),
+                match (with.compute_units, without.compute_units) {
+                    (Some(with), Some(without)) => {
+                        format!("{:+}", change(with as usize, without as usize))
+                    }
+                    _ => "-".to_string(),
+                }
+            );
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "diff": {
+                    "flag": diff_flag,
+                    "with": with,
+                    "without": without,
+                }
+            })
+        ),
+        OutputFormat::Shell => {
+            let mut vars = vec![
+                ("DIFF_FLAG", diff_flag.to_string()),
+                ("WITH_SIZE", with.size.to_string()),
+                ("WITHOUT_SIZE", without.size.to_string()),
+                ("WITH_ACCOUNTS", with.accounts.to_string()),
+                ("WITHOUT_ACCOUNTS", without.accounts.to_string()),
+            ];
+            if let Some(compute_units) = with.compute_units {
+                vars.push(("WITH_COMPUTE_UNITS", compute_units.to_string()));
+            }
+            if let Some(compute_units) = without.compute_units {
+                vars.push(("WITHOUT_COMPUTE_UNITS", compute_units.to_string()));
+            }
+            print_shell_vars(&vars);
+        }
+    }
+    Ok(())
+}
+
+/// For `--health-check`, fails unless the node reports itself healthy and, given a reference
+/// endpoint, its slot is within `max_slot_lag` of the reference's
+async fn health_check(
+    rpc_client: &FailoverRpcClient,
+    reference: Option<(&str, &FailoverRpcClient)>,
+    max_slot_lag: u64,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    rpc_client
+        .call("getHealth", |rpc_client| rpc_client.get_health())
+        .await
+        .map_err(|err| format!("error: node is unhealthy: {err}"))?;
+    let slot = rpc_client
+        .call("getSlot", |rpc_client| rpc_client.get_slot())
+        .await
+        .map_err(|err| format!("error: unable to get slot: {err}"))?;
+
+    match reference {
+        Some((reference_url, reference)) => {
+            let reference_slot = reference
+                .call("getSlot", |rpc_client| rpc_client.get_slot())
+                .await
+                .map_err(|err| format!("error: unable to get slot from {reference_url}: {err}"))?;
+            let lag = reference_slot.saturating_sub(slot);
+            if lag > max_slot_lag {
+                return Err(format!(
+                    "error: node is {lag} slots behind {reference_url} (slot {slot}, reference \
+                     slot {reference_slot}), over --max-slot-lag {max_slot_lag}"
+                )
+                .into());
+            }
+            if verbose {
+                println!(
+                    "Node is healthy at slot {slot}, {lag} slots behind {reference_url} at slot \
+                     {reference_slot}"
+                );
+            }
+        }
+        None => {
+            if verbose {
+                println!("Node is healthy at slot {slot}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An `--amount-file` amount
+#[derive(Debug, PartialEq)]
+enum FileAmount {
+    /// In lamports, or the mint's smallest unit
+    Units(u64),
+    /// In SOL, or the mint's tokens
+    Whole(f64),
+}
+
+impl FileAmount {
+    fn to_units(&self, token_mint: Option<&TokenMint>) -> u64 {
+        match (self, token_mint) {
+            (Self::Units(units), _) => *units,
+            (Self::Whole(amount), Some(token_mint)) => {
+                spl_token::ui_amount_to_amount(*amount, token_mint.decimals)
+            }
+            (Self::Whole(amount), None) => sol_to_lamports(*amount),
+        }
+    }
+}
+
+/// Parses an `--amount-file` amount: an integer is in lamports, or the mint's smallest unit, and
+/// a number with a decimal point is in SOL, or the mint's tokens
+fn parse_file_amount(amount: &str) -> Option<FileAmount> {
+    if let Ok(amount) = amount.parse::<u64>() {
+        return Some(FileAmount::Units(amount));
+    }
+    // Only plain decimals, as an exponent like 1e9 would otherwise be read as that many SOL
+    if !amount.contains('.') || !amount.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    amount.parse::<f64>().ok().map(FileAmount::Whole)
+}
+
+/// Reads the `--amount-file` amount
+fn read_amount_file(path: &str) -> Result<FileAmount, Box<dyn std::error::Error>> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("error: unable to read {path}: {err}"))?;
+    let amount = contents.trim();
+    parse_file_amount(amount)
+        .ok_or_else(|| format!("error: {path} should hold a single amount, not {amount:?}").into())
+}
+
+/// CoinGecko's SOL/USD price, the default `--price-source`
+const DEFAULT_PRICE_SOURCE: &str =
+    "https://api.coingecko.com/api/v3/simple/price?ids=solana&vs_currencies=usd";
+
+/// Converts `usd` to lamports at the SOL/USD price from `price_source`. The response may be a
+/// bare number, `{"price": <number>}`, or CoinGecko's `{"solana": {"usd": <number>}}`
+async fn usd_to_lamports(price_source: &str, usd: f64) -> Result<u64, Box<dyn std::error::Error>> {
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?
+        .get(price_source)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("error: unable to fetch the SOL/USD price: {err}"))?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|err| format!("error: unable to parse the SOL/USD price: {err}"))?;
+    let price = response
+        .as_f64()
+        .or_else(|| response["price"].as_f64())
+        .or_else(|| response["solana"]["usd"].as_f64())
+        .filter(|price| price.is_finite() && *price > 0.)
+        .ok_or_else(|| {
+            format!("error: no SOL/USD price in the response from {price_source}: {response}")
+        })?;
+    let lamports = sol_to_lamports(usd / price);
+    eprintln!(
+        "${usd} is {} at ${price} per SOL, from {price_source}",
+        Sol(lamports)
+    );
+    Ok(lamports)
+}
+
+/// Fetches `address` and checks that it is an SPL Token mint
+async fn get_token_mint(
+    rpc_client: &FailoverRpcClient,
+    address: Pubkey,
+) -> Result<TokenMint, Box<dyn std::error::Error>> {
+    let account = rpc_client
+        .call("getAccountInfo", |rpc_client| {
+            rpc_client.get_account(&address)
+        })
+        .await
+        .map_err(|err| format!("error: unable to get mint {address}: {err}"))?;
+    if account.owner != spl_token::id() {
+        return Err(format!("error: {address} is not an SPL Token mint").into());
+    }
+    let mint = spl_token::state::Mint::unpack(&account.data)
+        .map_err(|err| format!("error: {address} is not an SPL Token mint: {err}"))?;
+    Ok(TokenMint {
+        address,
+        decimals: mint.decimals,
+    })
+}
+
+/// Everything needed to build and send each transaction
+struct SendConfig<'a> {
+    rpc_client: FailoverRpcClient,
+    json_rpc_url: &'a str,
+    /// The fee payer, or with `--keypair-dir` the keypairs to pick each transaction's fee payer
+    /// from
+    signers: Vec<Box<dyn Signer>>,
+    /// Where each of `signers` was loaded from, with `--keypair-dir`
+    keypair_paths: Vec<String>,
+    /// From `--keypairs-file`, signing for whichever extra addresses they match
+    cosigners: Vec<Keypair>,
+    /// The first of `signers`
+    feepayer_address: Pubkey,
+    extra_addresses: Vec<Pubkey>,
+    system_program_id: Pubkey,
+    /// `--pad-to-size`, in bytes
+    pad_to_size: Option<usize>,
+    /// `--raw-instruction-data`, in place of the transfer instruction's data
+    raw_instruction_data: Option<Vec<u8>>,
+    compute_unit_price: Option<u64>,
+    priority_fee_auto: bool,
+    priority_multiplier: f64,
+    round_to: Option<u64>,
+    /// `--min-nonzero`, the least a random amount may be
+    min_nonzero: Option<u64>,
+    memo_template: Option<&'a str>,
+    idempotency_key: Option<&'a str>,
+    dry_run: bool,
+    /// `--fail-if-log-contains`
+    fail_log_patterns: Vec<&'a str>,
+    assert_effect: bool,
+    max_compute_units: Option<u64>,
+    estimate: bool,
+    /// `--estimate-for`, where the fee payer is only an address and the transaction stays unsigned
+    estimate_for: bool,
+    dump_transaction: bool,
+    message_hash: bool,
+    sign_only: bool,
+    emit_instructions: bool,
+    /// `--diff-flag`, the option left out of the second of two builds
+    diff_flag: Option<&'a str>,
+    external_signatures: Vec<(Pubkey, Signature)>,
+    amount: Option<u64>,
+    amount_cap: Option<u64>,
+    max_fraction: f64,
+    /// `--blockhash`, with its last valid block height when it came from a file
+    blockhash: Option<(Hash, Option<u64>)>,
+    blockhash_commitment: CommitmentConfig,
+    measure_latency: bool,
+    confirm_strategy: ConfirmStrategy,
+    confirm_commitment: CommitmentConfig,
+    /// `--ws-url`, or the websocket of the JSON RPC URL
+    ws_url: String,
+    confirm_rpc_retries: u32,
+    /// `--preflight-commitment`, or `None` for the client's commitment
+    preflight_commitment: Option<CommitmentConfig>,
+    verify: bool,
+    show_compute_units: bool,
+    fetch_details: bool,
+    assert_extra_unchanged: bool,
+    skip_underfunded: bool,
+    skip_balance_check: bool,
+    summary_csv: bool,
+    cost_basis: bool,
+    /// `--dump-failed` directory
+    dump_failed: Option<&'a Path>,
+    retry_on_any: bool,
+    max_retries: u32,
+    /// `--retry-budget`, shared by every transaction in the run
+    retry_budget: Option<u32>,
+    retries_used: AtomicU32,
+    max_fee: Option<u64>,
+    escalate_fee: bool,
+    escalation_factor: f64,
+    min_confirmations: Option<usize>,
+    confirm_timeout: Duration,
+    backoff: Duration,
+    /// `--retry-jitter full`
+    retry_jitter: bool,
+    output_format: OutputFormat,
+    signatures_only: bool,
+    /// `--template`, printed for each confirmed transaction instead of the usual output
+    template: Option<&'a str>,
+    verbose: bool,
+    open: bool,
+    /// `--compare-url` and a client for it
+    compare: Option<(&'a str, FailoverRpcClient)>,
+    to: Option<Pubkey>,
+    /// Transfer tokens of this mint rather than SOL
+    token_mint: Option<TokenMint>,
+    create_ata: bool,
+}
+
+impl SendConfig<'_> {
+    /// Takes a retry from `--retry-budget`, returning false once the budget is used up
+    fn take_retry(&self) -> bool {
+        match self.retry_budget {
+            Some(retry_budget) if self.retries_used.load(Ordering::Relaxed) >= retry_budget => {
+                false
+            }
+            _ => {
+                self.retries_used.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+        }
+    }
+
+    /// `--to`, or the fee payer itself
+    fn recipient(&self, feepayer_address: &Pubkey) -> Pubkey {
+        self.to.unwrap_or(*feepayer_address)
+    }
+
+    /// With `--mint`, the source and destination associated token accounts
+    fn token_accounts(
+        &self,
+        feepayer_address: &Pubkey,
+        recipient: &Pubkey,
+    ) -> Option<(Pubkey, Pubkey)> {
+        self.token_mint.as_ref().map(|token_mint| {
+            (
+                get_associated_token_address(feepayer_address, &token_mint.address),
+                get_associated_token_address(recipient, &token_mint.address),
+            )
+        })
+    }
+
+    /// Lowers `amount` to `--amount-cap`, if it is over
+    fn cap_amount(&self, amount: u64) -> u64 {
+        match self.amount_cap {
+            Some(amount_cap) if amount > amount_cap => {
+                if self.verbose {
+                    println!(
+                        "Amount {} capped to {}",
+                        self.display_amount(amount),
+                        self.display_amount(amount_cap)
+                    );
+                }
+                amount_cap
+            }
+            _ => amount,
+        }
+    }
+
+    /// Formats `amount` in SOL, or in tokens with `--mint`
+    fn display_amount(&self, amount: u64) -> String {
+        match &self.token_mint {
+            Some(token_mint) => format!(
+                "{} tokens",
+                spl_token::amount_to_ui_amount_string_trimmed(amount, token_mint.decimals)
+            ),
+            None => Sol(amount).to_string(),
+        }
+    }
+}
+
+enum Outcome {
+    Sent(SendResult),
+    Simulated,
+    /// Printed for an external signer instead of being signed, or signed and printed instead of
+    /// being sent with `--sign-only`, or printed as bare instructions with `--emit-instructions`
+    MessagePrinted,
+    Estimated {
+        lamports: u64,
+        fee: u64,
+        balance: u64,
+    },
+    /// Not sent because the fee is over `--max-fee`
+    FeeCapped {
+        fee: u64,
+    },
+    /// Not sent because the fee payer can't cover it, with `--skip-underfunded`
+    Underfunded {
+        required: u64,
+        balance: u64,
+    },
+    /// Sent to both the primary and the `--compare-url` endpoint
+    Compared,
+    /// Built and signed but neither sent nor simulated, for `--diff-flag`
+    Built(Box<Transaction>),
+}
+
+/// Placeholders of `--template`
+const TEMPLATE_PLACEHOLDERS: [&str; 6] = [
+    "signature",
+    "slot",
+    "fee",
+    "recipient",
+    "amount_sol",
+    "amount_lamports",
+];
+
+/// The names of the `{name}` placeholders in `template`
+fn template_placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+}
+
+fn is_output_template(template: &str) -> Result<(), String> {
+    match template_placeholders(template)
+        .find(|placeholder| !TEMPLATE_PLACEHOLDERS.contains(placeholder))
+    {
+        Some(unknown) => Err(format!(
+            "unknown placeholder {{{unknown}}}, expected one of {}",
+            TEMPLATE_PLACEHOLDERS
+                .map(|name| format!("{{{name}}}"))
+                .join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Expands the `--template` placeholders for one confirmed transaction. The slot and fee come
+/// from the confirmed transaction, and are left empty if it couldn't be fetched
+fn expand_output_template(
+    template: &str,
+    result: &SendResult,
+    recipient: &Pubkey,
+    lamports: u64,
+) -> String {
+    let (slot, fee) = result
+        .details
+        .as_ref()
+        .map_or_else(Default::default, |details| {
+            (details.slot.to_string(), details.fee_lamports.to_string())
+        });
+    template
+        .replace("{signature}", &result.signature)
+        .replace("{slot}", &slot)
+        .replace("{fee}", &fee)
+        .replace("{recipient}", &recipient.to_string())
+        .replace("{amount_sol}", &lamports_to_sol(lamports).to_string())
+        .replace("{amount_lamports}", &lamports.to_string())
+}
+
+/// What one transaction transfers
+#[derive(Clone)]
+struct Transfer {
+    /// Index of the fee payer in `SendConfig::signers`
+    signer: usize,
+    recipient: Pubkey,
+    lamports: u64,
+    memo: Option<String>,
+    extra_addresses: Vec<Pubkey>,
+}
+
+/// Picks the fee payer, amount and memo for one iteration
+async fn plan_transfer(
+    config: &SendConfig<'_>,
+    iteration: usize,
+    rng: &mut StdRng,
+) -> Result<Transfer, Box<dyn std::error::Error>> {
+    let rpc_client = &config.rpc_client;
+    let signer = rng.gen_range(0..config.signers.len());
+    let feepayer_address = config.signers[signer].pubkey();
+
+    let recipient = config.recipient(&feepayer_address);
+    // Only a random amount needs the balance
+    let lamports = match config.amount {
+        Some(amount) => amount,
+        None if config.skip_balance_check => {
+            return Err("error: --skip-balance-check requires a fixed amount".into());
+        }
+        None => {
+            let balance = match config.token_accounts(&feepayer_address, &recipient) {
+                Some((source, _)) => rpc_client
+                    .call("getTokenAccountBalance", |rpc_client| {
+                        rpc_client.get_token_account_balance(&source)
+                    })
+                    .await
+                    .map_err(|err| {
+                        format!("error: unable to get token balance of {source}: {err}")
+                    })?
+                    .amount
+                    .parse::<u64>()?,
+                None => {
+                    rpc_client
+                        .call("getBalance", |rpc_client| {
+                            rpc_client.get_balance(&feepayer_address)
+                        })
+                        .await?
+                }
+            };
+            let max_amount = (balance as f64 * config.max_fraction) as u64;
+            if max_amount == 0 {
+                return Err(format!(
+                    "error: balance of {} is too low to pick a random amount",
+                    config.display_amount(balance)
+                )
+                .into());
+            }
+            // Drawing from the floor up keeps the amounts spread out, unless the balance is too low
+            // for that
+            let min_amount = config.min_nonzero.unwrap_or_default();
+            if min_amount < max_amount {
+                rng.gen_range(min_amount..max_amount)
+            } else {
+                min_amount
+            }
+        }
+    };
+    // Rounding down keeps the amount within the balance
+    let lamports = match config.round_to {
+        Some(round_to) => lamports - lamports % round_to.max(1),
+        None => lamports,
+    };
+    let lamports = config.cap_amount(lamports);
+
+    let memo = config
+        .memo_template
+        .map(|memo_template| expand_memo_template(memo_template, iteration, lamports));
+    Ok(Transfer {
+        signer,
+        recipient,
+        lamports,
+        memo,
+        extra_addresses: config.extra_addresses.clone(),
+    })
+}
+
+/// The compute unit price `--escalate-fee` moves on to from `price`. It always goes up, even where
+/// multiplying a small price by `factor` would truncate back to the same price
+fn escalate_price(price: Option<u64>, factor: f64) -> u64 {
+    let price = price.unwrap_or_default();
+    ((price.max(1) as f64 * factor) as u64).max(price.saturating_add(1))
+}
+
+/// Longest wait before a `--retry-on-any` retry, however far the backoff has doubled
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Builds, signs and sends (or simulates, or estimates) the transaction for one iteration.
+/// With `warmup`, a sent transaction's result isn't printed
+async fn send_one(
+    config: &SendConfig<'_>,
+    transfer: &Transfer,
+    rng: &mut StdRng,
+    warmup: bool,
+) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let rpc_client = &config.rpc_client;
+    let signer = config.signers[transfer.signer].as_ref();
+    let feepayer_address = signer.pubkey();
+    let verbose = config.verbose;
+    let recipient = transfer.recipient;
+    let transfer_amount = transfer.lamports;
+
+    if verbose {
+        println!(
+            "Fee payer: {}, Amount: {}",
+            feepayer_address,
+            config.display_amount(transfer_amount)
+        );
+        if let Some(keypair_path) = config.keypair_paths.get(transfer.signer) {
+            println!("Fee payer keypair: {keypair_path}");
+        }
+        if recipient != feepayer_address {
+            println!("Recipient: {recipient}");
+        }
+        println!("Extra addresses: {:?}", transfer.extra_addresses);
+        if config.system_program_id != system_program::id() {
+            println!("System program override: {}", config.system_program_id);
+        }
+    }
+
+    let compute_unit_price = if config.priority_fee_auto {
+        let mut addresses = vec![feepayer_address];
+        addresses.extend(&transfer.extra_addresses);
+        match get_median_prioritization_fee(rpc_client, &addresses).await {
+            Ok(fee) => Some((fee as f64 * config.priority_multiplier) as u64),
+            Err(err) => {
+                eprintln!("warning: unable to get recent prioritization fees: {err}");
+                Some(0)
+            }
+        }
+    } else {
+        config.compute_unit_price
+    };
+
+    let mut instructions = vec![];
+    if let Some(compute_unit_price) = compute_unit_price {
+        if verbose {
+            println!("Compute unit price: {compute_unit_price} micro-lamports");
+        }
+        instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+            compute_unit_price,
+        ));
+    }
+    match (
+        &config.token_mint,
+        config.token_accounts(&feepayer_address, &recipient),
+    ) {
+        (Some(token_mint), Some((source, destination))) => {
+            let destination_exists = rpc_client
+                .call("getAccountInfo", |rpc_client| {
+                    rpc_client.get_account_with_commitment(&destination, rpc_client.commitment())
+                })
+                .await?
+                .value
+                .is_some();
+            if !destination_exists {
+                if !config.create_ata {
+                    return Err(format!(
+                        "error: {recipient} has no token account for {}, pass --create-ata to \
+                         create it",
+                        token_mint.address
+                    )
+                    .into());
+                }
+                instructions.push(create_associated_token_account(
+                    &feepayer_address,
+                    &recipient,
+                    &token_mint.address,
+                ));
+            }
+            let mut token_transfer = spl_token::instruction::transfer_checked(
+                &spl_token::id(),
+                &source,
+                &token_mint.address,
+                &destination,
+                &feepayer_address,
+                &[],
+                transfer_amount,
+                token_mint.decimals,
+            )?;
+            for extra_address in &transfer.extra_addresses {
+                token_transfer
+                    .accounts
+                    .push(AccountMeta::new_readonly(*extra_address, false));
+            }
+            instructions.push(token_transfer);
+        }
+        _ => {
+            let mut system_transfer = transfer_with(
+                &config.system_program_id,
+                &feepayer_address,
+                &recipient,
+                transfer_amount,
+                &transfer.extra_addresses,
+            );
+            if let Some(raw_instruction_data) = &config.raw_instruction_data {
+                system_transfer.data = raw_instruction_data.clone();
+            }
+            instructions.push(system_transfer);
+        }
+    }
+    // The message then requires their signatures, which are added when signing
+    for account in instructions
+        .iter_mut()
+        .flat_map(|instruction| instruction.accounts.iter_mut())
+    {
+        if config
+            .cosigners
+            .iter()
+            .any(|cosigner| cosigner.pubkey() == account.pubkey)
+        {
+            account.is_signer = true;
+        }
+    }
+    if let Some(memo) = &transfer.memo {
+        if memo.len() > MAX_MEMO_LEN {
+            return Err(format!(
+                "error: memo is {} bytes, the maximum is {MAX_MEMO_LEN}",
+                memo.len()
+            )
+            .into());
+        }
+        if verbose {
+            println!("Memo: {memo}");
+        }
+        instructions.push(memo_instruction(memo));
+    }
+    // Retries re-sign this same message, so every attempt carries the same key
+    if let Some(idempotency_key) = config.idempotency_key {
+        if idempotency_key.len() > MAX_MEMO_LEN {
+            return Err(format!(
+                "error: idempotency key is {} bytes, the maximum is {MAX_MEMO_LEN}",
+                idempotency_key.len()
+            )
+            .into());
+        }
+        instructions.push(memo_instruction(idempotency_key));
+    }
+    if let Some(pad_to_size) = config.pad_to_size {
+        let mut padded = instructions.clone();
+        padded.push(memo_instruction(""));
+        let size = bincode::serialized_size(&Transaction::new_unsigned(Message::new(
+            &padded,
+            Some(&feepayer_address),
+        )))? as usize;
+        let padding = pad_to_size.checked_sub(size).ok_or_else(|| {
+            format!(
+                "error: the transaction is {size} bytes without padding, over --pad-to-size \
+                 {pad_to_size}"
+            )
+        })?;
+        // The memo's length prefix takes a second byte from 128 bytes of memo on
+        let padding = if padding > 128 {
+            padding - 1
+        } else {
+            padding.min(127)
+        };
+        if verbose {
+            println!("Padding: {padding} byte memo");
+        }
+        instructions.push(memo_instruction(&"x".repeat(padding)));
+    }
+
+    if config.emit_instructions {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&instructions_to_json(&instructions))?
+        );
+        return Ok(Outcome::MessagePrinted);
+    }
+
+    let mut transaction =
+        Transaction::new_unsigned(Message::new(&instructions, Some(&feepayer_address)));
+    if verbose {
+        print_account_keys(&transaction.message);
+    }
+
+    let blockhash_start = Instant::now();
+    let (blockhash, mut last_valid_block_height) = match config.blockhash {
+        Some((blockhash, last_valid_block_height)) => (blockhash, last_valid_block_height),
+        None => rpc_client
+            .call("getLatestBlockhash", |rpc_client| {
+                rpc_client.get_latest_blockhash_with_commitment(config.blockhash_commitment)
+            })
+            .await
+            .map(|(blockhash, last_valid_block_height)| (blockhash, Some(last_valid_block_height)))
+            .map_err(|err| format!("error: unable to get latest blockhash: {err}"))?,
+    };
+    let mut blockhash_elapsed = blockhash_start.elapsed();
+
+    if config.message_hash {
+        transaction.message.recent_blockhash = blockhash;
+        print_message(&transaction.message, transfer_amount, config.output_format)?;
+        return Ok(Outcome::MessagePrinted);
+    }
+
+    if config.estimate_for {
+        // The fee doesn't depend on the signatures, which the fee payer can't make
+        transaction.message.recent_blockhash = blockhash;
+    } else if config.external_signatures.is_empty() {
+        let signers = required_signers(&transaction.message, signer, &config.cosigners);
+        transaction
+            .try_sign(&signers, blockhash)
+            .map_err(|err| format!("error: failed to sign transaction: {err}"))?;
+    } else {
+        attach_signatures(&mut transaction, &config.external_signatures, blockhash)?;
+    }
+    // Catches a signer that signed for the wrong key before the RPC node does
+    if config.verify && !config.estimate_for {
+        verify_signatures(&transaction)?;
+    }
+
+    if config.diff_flag.is_some() {
+        return Ok(Outcome::Built(Box::new(transaction)));
+    }
+
+    if config.sign_only {
+        print_signed_transaction(&transaction, config.output_format)?;
+        return Ok(Outcome::MessagePrinted);
+    }
+
+    let fee = if config.estimate || config.max_fee.is_some() || config.skip_underfunded {
+        Some(
+            rpc_client
+                .call("getFeeForMessage", |rpc_client| {
+                    rpc_client.get_fee_for_message(&transaction.message)
+                })
+                .await
+                .map_err(|err| format!("error: unable to get fee: {err}"))?,
+        )
+    } else {
+        None
+    };
+
+    if let (Some(fee), Some(max_fee)) = (fee, config.max_fee) {
+        if fee > max_fee {
+            return Ok(Outcome::FeeCapped { fee });
+        }
+    }
+
+    if let (true, Some(fee)) = (config.skip_underfunded, fee) {
+        let balance = rpc_client
+            .call("getBalance", |rpc_client| {
+                rpc_client.get_balance(&feepayer_address)
+            })
+            .await?;
+        // Token transfers only need the fee in SOL
+        let required = if config.token_mint.is_none() {
+            fee.saturating_add(transfer_amount)
+        } else {
+            fee
+        };
+        if balance < required {
+            return Ok(Outcome::Underfunded { required, balance });
+        }
+    }
+
+    if let (true, Some(fee)) = (config.estimate, fee) {
+        let balance = rpc_client
+            .call("getBalance", |rpc_client| {
+                rpc_client.get_balance(&feepayer_address)
+            })
+            .await?;
+        return Ok(Outcome::Estimated {
+            lamports: transfer_amount,
+            fee,
+            balance,
+        });
+    }
+
+    if config.dump_transaction {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&transaction_to_json(&transaction))?
+        );
+    }
+
+    if config.assert_effect {
+        // Token transfers leave the fee payer's SOL alone
+        let transferred = if config.token_mint.is_none() && recipient != feepayer_address {
+            transfer_amount
+        } else {
+            0
+        };
+        // A transfer back to the fee payer leaves no other account to be credited
+        let credit = match config.token_accounts(&feepayer_address, &recipient) {
+            Some((source, destination)) => (source != destination).then_some(Credit {
+                address: destination,
+                token: true,
+                amount: transfer_amount,
+            }),
+            None => (recipient != feepayer_address).then_some(Credit {
+                address: recipient,
+                token: false,
+                amount: transfer_amount,
+            }),
+        };
+        assert_effect(
+            rpc_client,
+            &transaction,
+            &feepayer_address,
+            transferred,
+            credit,
+            verbose,
+        )
+        .await?;
+    }
+
+    if let Some(max_compute_units) = config.max_compute_units {
+        check_compute_units(rpc_client, &transaction, max_compute_units, verbose).await?;
+    }
+
+    if config.dry_run {
+        let result = rpc_client
+            .call("simulateTransaction", |rpc_client| {
+                rpc_client.simulate_transaction(&transaction)
+            })
+            .await
+            .map_err(|err| format!("error: unable to simulate transaction: {err}"))?
+            .value;
+
+        let logs = result.logs.unwrap_or_default();
+        if verbose {
+            for log in &logs {
+                println!("Log: {log}");
+            }
+        }
+        if let Some(err) = result.err {
+            return Err(format!("error: simulation failed: {err}").into());
+        }
+        for pattern in &config.fail_log_patterns {
+            if let Some(log) = logs.iter().find(|log| log.contains(pattern)) {
+                return Err(format!("error: simulation log matches {pattern:?}: {log}").into());
+            }
+        }
+        println!("Simulation succeeded");
+        return Ok(Outcome::Simulated);
+    }
+
+    if config.compare.is_some() {
+        compare_endpoints(config, &transaction, last_valid_block_height).await?;
+        return Ok(Outcome::Compared);
+    }
+
+    let mut attempt = 0;
+    let mut resign = false;
+    let mut compute_unit_price = compute_unit_price;
+    // The signature of every attempt, any of which may yet land
+    let mut submitted = vec![];
+    let (signature, elapsed) = loop {
+        let result = async {
+            if resign {
+                // Sending again once an earlier attempt has landed would transfer twice
+                if let Some((signature, result)) = landed_signature(rpc_client, &submitted).await? {
+                    result?;
+                    eprintln!("Earlier attempt {signature} landed, not sending again");
+                    return Ok((signature, None));
+                }
+                let blockhash_start = Instant::now();
+                let (blockhash, block_height) = rpc_client
+                    .call("getLatestBlockhash", |rpc_client| {
+                        rpc_client.get_latest_blockhash_with_commitment(config.blockhash_commitment)
+                    })
+                    .await?;
+                last_valid_block_height = Some(block_height);
+                blockhash_elapsed = blockhash_start.elapsed();
+                let signers = required_signers(&transaction.message, signer, &config.cosigners);
+                transaction.try_sign(&signers, blockhash)?;
+                if config.verify {
+                    transaction.verify()?;
+                }
+            }
+            if config.escalate_fee {
+                match tokio::time::timeout(
+                    config.confirm_timeout,
+                    send_and_confirm(
+                        config,
+                        &transaction,
+                        last_valid_block_height,
+                        &mut submitted,
+                    ),
+                )
+                .await
+                {
+                    Ok(result) => result,
+                    Err(_) => Err(RpcError::ForUser("confirmation timed out".to_string()).into()),
+                }
+            } else {
+                send_and_confirm(
+                    config,
+                    &transaction,
+                    last_valid_block_height,
+                    &mut submitted,
+                )
+                .await
+            }
+        }
+        .await;
+
+        match result {
+            Ok(sent) => break sent,
+            // Unconfirmed transactions surface as `ForUser` errors, whether the wait timed out or
+            // the blockhash expired
+            Err(ClientError {
+                kind: ClientErrorKind::RpcError(RpcError::ForUser(reason)),
+                ..
+            }) if config.escalate_fee => {
+                let escalated_price = escalate_price(compute_unit_price, config.escalation_factor);
+                let set_compute_unit_price =
+                    ComputeBudgetInstruction::set_compute_unit_price(escalated_price);
+                if compute_unit_price.is_some() {
+                    instructions[0] = set_compute_unit_price;
+                } else {
+                    instructions.insert(0, set_compute_unit_price);
+                }
+                compute_unit_price = Some(escalated_price);
+                transaction =
+                    Transaction::new_unsigned(Message::new(&instructions, Some(&feepayer_address)));
+
+                let fee = rpc_client
+                    .call("getFeeForMessage", |rpc_client| {
+                        rpc_client.get_fee_for_message(&transaction.message)
+                    })
+                    .await
+                    .map_err(|err| format!("error: unable to get fee: {err}"))?;
+                let max_fee = config.max_fee.unwrap_or_default();
+                if fee > max_fee {
+                    return Err(format!(
+                        "error: {reason}, and escalating the fee to {} would exceed --max-fee {}",
+                        Sol(fee),
+                        Sol(max_fee)
+                    )
+                    .into());
+                }
+                eprintln!(
+                    "{reason}, escalating the compute unit price to {escalated_price} \
+                     micro-lamports (fee {})",
+                    Sol(fee)
+                );
+                resign = true;
+            }
+            Err(err)
+                if config.retry_on_any
+                    && attempt < config.max_retries
+                    && is_transient_error(&err)
+                    && config.take_retry() =>
+            {
+                let delay = 2_u32
+                    .checked_pow(attempt)
+                    .and_then(|factor| config.backoff.checked_mul(factor))
+                    .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+                // Full jitter: anywhere from no delay to the whole backoff
+                let delay = if config.retry_jitter {
+                    delay.mul_f64(rng.gen())
+                } else {
+                    delay
+                };
+                attempt += 1;
+                resign = true;
+                if verbose {
+                    println!("Attempt {attempt} failed: {err}. Retrying in {delay:?}");
+                }
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => {
+                let err = match err.kind() {
+                    ClientErrorKind::TransactionError(_) => {
+                        format!("error: transaction failed: {err}")
+                    }
+                    _ => format!("error: send transaction: {err}"),
+                };
+                if let Some(dir) = config.dump_failed {
+                    match dump_failed_transaction(dir, &transaction, &err) {
+                        Ok(path) => eprintln!("Failed transaction written to {}", path.display()),
+                        Err(dump_err) => eprintln!(
+                            "warning: unable to write failed transaction to {}: {dump_err}",
+                            dir.display()
+                        ),
+                    }
+                }
+                return Err(err.into());
+            }
+        }
+    };
+
+    if let Some(min_confirmations) = config.min_confirmations {
+        wait_for_confirmations(
+            rpc_client,
+            &signature,
+            min_confirmations,
+            config.confirm_timeout,
+            verbose,
+        )
+        .await?;
+    }
+
+    let latency = elapsed.map(|(send_elapsed, total_elapsed)| Latency {
+        blockhash_ms: duration_ms(blockhash_elapsed),
+        send_ms: duration_ms(send_elapsed),
+        confirmation_ms: duration_ms(total_elapsed - send_elapsed),
+        total_ms: duration_ms(total_elapsed),
+    });
+
+    // Shell output, --summary-csv and --cost-basis always report the fee, which comes from the
+    // confirmed transaction
+    let fetch_details = config.fetch_details
+        || config.summary_csv
+        || config.cost_basis
+        || config.output_format == OutputFormat::Shell
+        || config.template.is_some_and(|template| {
+            template_placeholders(template).any(|name| name == "slot" || name == "fee")
+        });
+    let confirmed_transaction =
+        if config.show_compute_units || fetch_details || config.assert_extra_unchanged {
+            let confirmed_transaction = get_confirmed_transaction(rpc_client, &signature).await;
+            if confirmed_transaction.is_none() {
+                eprintln!("warning: unable to fetch confirmed transaction {signature}");
+            }
+            confirmed_transaction
+        } else {
+            None
+        };
+    let compute_units = confirmed_transaction
+        .as_ref()
+        .filter(|_| config.show_compute_units)
+        .and_then(|transaction| transaction.transaction.meta.as_ref())
+        .and_then(|meta| meta.log_messages.as_ref())
+        .and_then(|logs| compute_units_from_logs(logs));
+    let details = confirmed_transaction
+        .as_ref()
+        .filter(|_| fetch_details)
+        .and_then(TransactionDetails::new);
+
+    if config.assert_extra_unchanged {
+        let details = confirmed_transaction
+            .as_ref()
+            .and_then(TransactionDetails::new)
+            .ok_or_else(|| format!("error: unable to check the extra addresses of {signature}"))?;
+        assert_extra_unchanged(&signature, &details, &transfer.extra_addresses)?;
+    }
+
+    let result = SendResult {
+        signature: signature.to_string(),
+        idempotency_key: config.idempotency_key.map(|key| key.to_string()),
+        latency,
+        compute_units,
+        details,
+    };
+    // A --warmup transaction is sent like any other, but not reported
+    if warmup {
+        return Ok(Outcome::Sent(result));
+    }
+    match config.output_format {
+        OutputFormat::Text if config.signatures_only => println!("{}", result.signature),
+        OutputFormat::Text if config.template.is_some() => println!(
+            "{}",
+            expand_output_template(
+                config.template.unwrap(),
+                &result,
+                &recipient,
+                transfer_amount
+            )
+        ),
+        OutputFormat::Text => {
+            println!("Signature: {}", result.signature);
+            if let Some(idempotency_key) = &result.idempotency_key {
+                println!("Idempotency key: {idempotency_key}");
+            }
+            if let Some(latency) = &result.latency {
+                println!(
+                    "Latency: blockhash {:.1}ms, send {:.1}ms, confirmation {:.1}ms, total {:.1}ms",
+                    latency.blockhash_ms,
+                    latency.send_ms,
+                    latency.confirmation_ms,
+                    latency.total_ms
+                );
+            }
+            if config.show_compute_units {
+                // Native programs don't log their consumption, so this is only part of the total
+                match result.compute_units {
+                    Some(compute_units) => {
+                        println!("Compute units logged by BPF programs: {compute_units}")
+                    }
+                    None => println!("Compute units logged by BPF programs: none"),
+                }
+            }
+            if let (true, Some(details)) = (config.fetch_details, &result.details) {
+                println!("Slot: {}", details.slot);
+                println!("Fee: {}", Sol(details.fee_lamports));
+                println!("Balance changes:");
+                for balance in &details.balances {
+                    println!(
+                        "  {}: {} -> {}",
+                        balance.address,
+                        Sol(balance.pre_lamports),
+                        Sol(balance.post_lamports)
+                    );
+                }
+                println!("Instructions:");
+                for (index, instruction) in details.instructions.iter().enumerate() {
+                    println!("  {index}: {instruction}");
+                }
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string(&result)?),
+        OutputFormat::Shell => {
+            let mut vars = vec![("SIGNATURE", result.signature.clone())];
+            if let Some(idempotency_key) = &result.idempotency_key {
+                vars.push(("IDEMPOTENCY_KEY", idempotency_key.clone()));
+            }
+            if let Some(details) = &result.details {
+                vars.push(("SLOT", details.slot.to_string()));
+                vars.push(("FEE_LAMPORTS", details.fee_lamports.to_string()));
+            }
+            if let Some(compute_units) = result.compute_units {
+                vars.push(("COMPUTE_UNITS", compute_units.to_string()));
+            }
+            if let Some(latency) = &result.latency {
+                vars.push(("LATENCY_MS", format!("{:.1}", latency.total_ms)));
+            }
+            print_shell_vars(&vars);
+        }
+    }
+
+    if config.open {
+        match explorer_url(config.json_rpc_url, &signature) {
+            Some(explorer_url) => {
+                if verbose {
+                    println!("Explorer: {explorer_url}");
+                }
+                if let Err(err) = webbrowser::open(&explorer_url) {
+                    eprintln!("warning: unable to open {explorer_url}: {err}");
+                }
+            }
+            None => eprintln!(
+                "warning: no explorer link for {}, not opening",
+                config.json_rpc_url
+            ),
+        }
+    }
+
+    Ok(Outcome::Sent(result))
+}
+
+pub fn command() -> Command<'static> {
+    Command::new("send")
+        .about("Transfer a random amount from the fee payer back to itself")
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("1")
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(count) if count > 0 => Ok(()),
+                    Ok(_) => Err("must be at least 1".to_string()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help(
+                    "Number of transactions to send, one after another. A failed transaction \
+                     doesn't stop the run, but makes the exit status non-zero",
+                ),
+        )
+        .arg(
+            Arg::new("warmup")
+                .long("warmup")
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .conflicts_with_all(&[
+                    "replay",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "message_hash",
+                    "sign_only",
+                    "emit_instructions",
+                    "diff_flag",
+                    "idempotency_key",
+                ])
+                .help(
+                    "Before the measured transactions, send N more with the same arguments and \
+                     leave them out of the latencies, summary and every other report, so the \
+                     numbers reflect a warmed-up connection. Requires --measure-latency or a \
+                     --count over 1",
+                ),
+        )
+        .arg(
+            Arg::new("ramp_from")
+                .long("ramp-from")
+                .value_name("TPS")
+                .takes_value(true)
+                .requires_all(&["count", "ramp_to", "ramp_duration"])
+                .conflicts_with("target_tps")
+                .validator(is_positive_number)
+                .help("Start a ramp of the transaction rate at this many per second"),
+        )
+        .arg(
+            Arg::new("ramp_to")
+                .long("ramp-to")
+                .value_name("TPS")
+                .takes_value(true)
+                .requires("ramp_from")
+                .validator(is_positive_number)
+                .help("Rate the ramp reaches after --ramp-duration, and holds from then on"),
+        )
+        .arg(
+            Arg::new("ramp_duration")
+                .long("ramp-duration")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .requires("ramp_from")
+                .validator(|s| match s.parse::<u64>() {
+                    Ok(duration) if duration > 0 => Ok(()),
+                    Ok(_) => Err("must be at least 1".to_string()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help(
+                    "How long the ramp takes to go from --ramp-from to --ramp-to. The summary \
+                     reports the rate achieved over each tenth of this",
+                ),
+        )
+        .arg(
+            Arg::new("spread_over")
+                .long("spread-over")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .requires("count")
+                .conflicts_with_all(&["target_tps", "ramp_from"])
+                .validator(|s| {
+                    is_positive_number(s)?;
+                    // Rules out infinity and windows too long to wait for
+                    Duration::try_from_secs_f64(s.parse().unwrap())
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .help(
+                    "Spread the --count transactions evenly over this many seconds, starting \
+                     one every SECONDS/N. Warns if sending takes longer",
+                ),
+        )
+        .arg(
+            Arg::new("target_tps")
+                .long("target-tps")
+                .value_name("TPS")
+                .takes_value(true)
+                .validator(is_positive_number)
+                .help(
+                    "Pace the start of each transaction to approximately this many per second. \
+                     Each transaction is confirmed before the next is sent, so the achieved \
+                     rate can be lower",
+                ),
+        )
+        .arg(
+            Arg::new("open")
+                .long("open")
+                .conflicts_with("count")
+                .takes_value(false)
+                .help("Open the transaction in Solana Explorer once it's confirmed"),
+        )
+        .arg(
+            Arg::new("compare_url")
+                .long("compare-url")
+                .value_name("URL")
+                .takes_value(true)
+                .validator(|s| Url::parse(s).map(|_| ()).map_err(|err| err.to_string()))
+                .conflicts_with_all(&[
+                    "count",
+                    "open",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "message_hash",
+                    "retry_on_any",
+                    "escalate_fee",
+                    "signatures_only",
+                ])
+                .help(
+                    "Send the transaction to this RPC endpoint as well as the primary one, at the \
+                     same time, and report how quickly each accepted and confirmed it",
+                ),
+        )
+        .arg(
+            Arg::new("raw_transaction")
+                .long("raw-transaction")
+                .value_name("BASE64")
+                .takes_value(true)
+                .conflicts_with("validate_only")
+                .help(
+                    "Submit this signed, base64-encoded transaction byte-for-byte as given, \
+                     without building or signing anything, then exit",
+                ),
+        )
+        .arg(
+            Arg::new("validate_only")
+                .long("validate-only")
+                .takes_value(false)
+                .help(
+                    "Check the arguments, signer and RPC URL, then exit without using the network",
+                ),
+        )
+        .arg(
+            Arg::new("check_signers")
+                .long("check-signers")
+                .takes_value(false)
+                .conflicts_with_all(&["add_signature", "message_hash", "emit_instructions"])
+                .help(
+                    "Before building the transaction, have each signer sign a test message, \
+                     prompting a hardware wallet once, and exit with an error if any can't",
+                ),
+        )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry-run")
+                .takes_value(false)
+                .help("Simulate the transaction instead of sending it"),
+        )
+        .arg(
+            Arg::new("fail_if_log_contains")
+                .long("fail-if-log-contains")
+                .value_name("PATTERN")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .requires("dry_run")
+                .help(
+                    "With --dry-run, fail if any simulation log line contains PATTERN, even \
+                     when the simulation succeeds. May be given more than once",
+                ),
+        )
+        .arg(
+            Arg::new("assert_effect")
+                .long("assert-effect")
+                .takes_value(false)
+                .help(
+                    "Simulate the transaction first and abort unless the simulated balance \
+                     changes of the fee payer and the recipient match the transfer",
+                ),
+        )
+        .arg(
+            Arg::new("max_compute_units")
+                .long("max-compute-units")
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help(
+                    "Simulate the transaction first and abort if it would consume more than N \
+                     compute units",
+                ),
+        )
+        .arg(
+            Arg::new("estimate")
+                .long("estimate")
+                .takes_value(false)
+                .conflicts_with_all(&["dry_run", "assert_effect", "max_compute_units"])
+                .help(
+                    "Print the total amount and fees the transaction would cost, and whether \
+                     the fee payer can cover them, without sending it",
+                ),
+        )
+        .arg(
+            Arg::new("estimate_for")
+                .long("estimate-for")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .validator(|s| is_valid_pubkey(s))
+                .conflicts_with_all(&[
+                    "estimate",
+                    "plan",
+                    "dry_run",
+                    "assert_effect",
+                    "max_compute_units",
+                    "keypair_dir",
+                    "keypairs_file",
+                    "add_signature",
+                    "check_signers",
+                ])
+                .help(
+                    "Like --estimate, but with PUBKEY as the fee payer, building the transaction \
+                     without its keypair, for a fee payer that will sign elsewhere",
+                ),
+        )
+        .arg(
+            Arg::new("plan")
+                .long("plan")
+                .takes_value(false)
+                .conflicts_with_all(&["dry_run", "assert_effect", "max_compute_units", "estimate"])
+                .help(
+                    "Print each transfer that would be sent, with its recipient, amount and fee, \
+                     and the total required, then exit without sending anything",
+                ),
+        )
+        .arg(
+            Arg::new("dump_transaction")
+                .long("dump-transaction")
+                .takes_value(false)
+                .help("Print the signed transaction as JSON before sending it"),
+        )
+        .arg(
+            Arg::new("message_hash")
+                .long("message-hash")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "count",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "assert_effect",
+                    "max_compute_units",
+                    "max_fee",
+                ])
+                .help(
+                    "Print the serialized message and its SHA-256 hash instead of signing and \
+                     sending it. The message bytes are exactly what each signer signs with \
+                     ed25519; the hash lets an external signer check it has the same bytes. \
+                     --keypair may be just the fee payer's address",
+                ),
+        )
+        .arg(
+            Arg::new("diff_flag")
+                .long("diff-flag")
+                .value_name("OPTION")
+                .takes_value(true)
+                .possible_values(["extra-addresses", "memo"])
+                .conflicts_with_all(&[
+                    "count",
+                    "replay",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "message_hash",
+                    "sign_only",
+                    "emit_instructions",
+                    "add_signature",
+                    "compare_url",
+                ])
+                .help(
+                    "Build the transaction with and without the extra addresses or the \
+                     --memo-template memo, simulate both, and print how their size, account \
+                     count and compute units differ. Nothing is sent",
+                ),
+        )
+        .arg(
+            Arg::new("emit_instructions")
+                .long("emit-instructions")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "count",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "assert_effect",
+                    "max_compute_units",
+                    "max_fee",
+                    "message_hash",
+                    "sign_only",
+                    "add_signature",
+                ])
+                .help(
+                    "Print the transaction's instructions as JSON, with each account's signer \
+                     and writable flags and the data in base64, then exit without building a \
+                     message or signing. --keypair may be just the fee payer's address",
+                ),
+        )
+        .arg(
+            Arg::new("sign_only")
+                .long("sign-only")
+                .takes_value(false)
+                .requires_all(&["amount", "blockhash"])
+                .conflicts_with_all(&[
+                    "count",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "assert_effect",
+                    "max_compute_units",
+                    "max_fee",
+                    "message_hash",
+                    "priority_fee_auto",
+                    "mint",
+                ])
+                .help(
+                    "Sign the transaction and print it base64-encoded instead of sending it, as \
+                     --raw-transaction takes it. With --amount and --blockhash nothing is asked \
+                     of the RPC node, so the same keypair, amount and blockhash always give the \
+                     same bytes",
+                ),
+        )
+        .arg(
+            Arg::new("add_signature")
+                .long("add-signature")
+                .value_name("PUBKEY:SIGNATURE")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .validator(|s| parse_pubkey_signature(s).map(|_| ()))
+                .conflicts_with_all(&["message_hash", "count", "retry_on_any"])
+                .requires_all(&["amount", "blockhash"])
+                .help(
+                    "Attach a signature produced outside of burri instead of signing, such as \
+                     one made over the output of --message-hash. The signature is checked \
+                     against the message before sending. May be given more than once",
+                ),
+        )
+        .arg(
+            Arg::new("amount")
+                .long("amount")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(|s| is_amount(s))
+                .conflicts_with("round_to")
+                .help("Transfer this amount instead of a random one"),
+        )
+        .arg(
+            Arg::new("health_check")
+                .long("health-check")
+                .takes_value(false)
+                .help(
+                    "Before sending, check that the node reports itself healthy, and with \
+                     --reference-url that it isn't too far behind. Fails if not",
+                ),
+        )
+        .arg(
+            Arg::new("reference_url")
+                .long("reference-url")
+                .value_name("URL")
+                .takes_value(true)
+                .requires("health_check")
+                .validator(|s| is_url_or_moniker(s))
+                .help(
+                    "JSON RPC URL or moniker of a node to compare slots with for --health-check. \
+                     None of the --header or --rpc-token headers are sent to it",
+                ),
+        )
+        .arg(
+            Arg::new("max_slot_lag")
+                .long("max-slot-lag")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .default_value("150")
+                .validator(is_parsable::<u64>)
+                .requires("reference_url")
+                .help("How far behind --reference-url the node may be for --health-check"),
+        )
+        .arg(
+            Arg::new("amount_file")
+                .long("amount-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "amount",
+                    "usd",
+                    "round_to",
+                    "min_nonzero",
+                    "replay",
+                ])
+                .help(
+                    "Transfer the amount in this file instead of a random one. An integer is in \
+                     lamports and a number with a decimal point in SOL; with --mint, in the \
+                     token's smallest unit and in tokens",
+                ),
+        )
+        .arg(
+            Arg::new("usd")
+                .long("usd")
+                .value_name("AMOUNT")
+                .takes_value(true)
+                .validator(|s| is_amount(s))
+                .conflicts_with_all(&[
+                    "amount",
+                    "round_to",
+                    "min_nonzero",
+                    "replay",
+                    "mint",
+                    "sign_only",
+                ])
+                .help(
+                    "Transfer this many US dollars' worth of SOL, at the price fetched from \
+                     --price-source before sending",
+                ),
+        )
+        .arg(
+            Arg::new("price_source")
+                .long("price-source")
+                .value_name("URL")
+                .takes_value(true)
+                .default_value(DEFAULT_PRICE_SOURCE)
+                .validator(|s| Url::parse(s).map(|_| ()).map_err(|err| err.to_string()))
+                .requires("usd")
+                .help(
+                    "Where --usd gets the SOL/USD price. The response must be JSON: a bare \
+                     number, {\"price\": <number>}, or CoinGecko's simple price",
+                ),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .validator(|s| is_valid_pubkey(s))
+                .help("Send the transfer to this address instead of back to the fee payer"),
+        )
+        .arg(
+            Arg::new("to_pda")
+                .long("to-pda")
+                .value_name("PROGRAM_ID")
+                .takes_value(true)
+                .conflicts_with("to")
+                .validator(|s| is_valid_pubkey(s))
+                .help(
+                    "Send the transfer to the program derived address of this program for the \
+                     --pda-seed seeds",
+                ),
+        )
+        .arg(
+            Arg::new("pda_seed")
+                .long("pda-seed")
+                .value_name("BASE64")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .requires("to_pda")
+                .validator(|s| base64::decode(s).map(|_| ()))
+                .help("Seed of the --to-pda address, in order. May be given more than once"),
+        )
+        .arg(
+            Arg::new("mint")
+                .long("mint")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .validator(|s| is_valid_pubkey(s))
+                .conflicts_with_all(&[
+                    "round_to",
+                    "system_program_id",
+                    "estimate",
+                    "plan",
+                    "message_hash",
+                    "add_signature",
+                ])
+                .help(
+                    "Transfer SPL tokens of this mint between associated token accounts instead \
+                     of SOL. --amount is then in tokens",
+                ),
+        )
+        .arg(
+            Arg::new("create_ata")
+                .long("create-ata")
+                .takes_value(false)
+                .requires("mint")
+                .conflicts_with("assert_effect")
+                .help("With --mint, create the recipient's associated token account if needed"),
+        )
+        .arg(
+            Arg::new("keypair_dir")
+                .long("keypair-dir")
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "message_hash",
+                    "add_signature",
+                    "airdrop",
+                    "wait_for_balance",
+                    "replay",
+                ])
+                .help(
+                    "Pay for each transaction from a keypair picked at random from the *.json \
+                     keypair files in this directory",
+                ),
+        )
+        .arg(
+            Arg::new("keypairs_file")
+                .long("keypairs-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with("add_signature")
+                .help(
+                    "JSON array of keypairs, each a 64-byte array as in a keypair file. Any extra \
+                     address with a keypair here becomes a signer of the transaction, and signs \
+                     it along with the fee payer",
+                ),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("SEED")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help(
+                    "Seed the random number generator that picks amounts and --keypair-dir fee \
+                     payers, so a run can be repeated",
+                ),
+        )
+        .arg(
+            Arg::new("skip_balance_check")
+                .long("skip-balance-check")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "assert_effect",
+                    "estimate",
+                    "estimate_for",
+                    "plan",
+                    "skip_underfunded",
+                    "wait_for_balance",
+                    "airdrop",
+                ])
+                .help(
+                    "Never fetch the fee payer's balance, so a flaky balance query can't stop a \
+                     send of a fixed --amount, --amount-file, --usd or --replay amount. Rules out \
+                     the options that need the balance",
+                ),
+        )
+        .arg(
+            Arg::new("amount_cap")
+                .long("amount-cap")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(|s| is_amount(s))
+                .help(
+                    "Never transfer more than this, whatever amount was picked or replayed. With \
+                     --mint, in tokens",
+                ),
+        )
+        .arg(
+            Arg::new("max_fraction")
+                .long("max-fraction")
+                .value_name("FRACTION")
+                .takes_value(true)
+                .default_value("0.5")
+                .conflicts_with("amount")
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(fraction) if fraction > 0. && fraction <= 1. => Ok(()),
+                    Ok(_) => Err("must be greater than 0 and at most 1".to_string()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help("Upper bound of the random amount, as a fraction of the fee payer's balance"),
+        )
+        .arg(
+            Arg::new("blockhash")
+                .long("blockhash")
+                .value_name("BLOCKHASH")
+                .takes_value(true)
+                .validator(|s| is_hash(s).or_else(|_| read_blockhash_file(s).map(|_| ())))
+                .conflicts_with("retry_on_any")
+                .help(
+                    "Use this recent blockhash instead of fetching the latest one. May be a file \
+                     written by `blockhash --write-blockhash`, which also gives the blockhash's \
+                     last valid block height",
+                ),
+        )
+        .arg(
+            Arg::new("wait_for_balance")
+                .long("wait-for-balance")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(|s| is_amount(s))
+                .help(
+                    "Wait until the fee payer's balance is at least this many SOL before sending",
+                ),
+        )
+        .arg(
+            Arg::new("wait_timeout")
+                .long("wait-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("60")
+                .validator(is_parsable::<u64>)
+                .help("How long --wait-for-balance and --airdrop wait before giving up"),
+        )
+        .arg(
+            Arg::new("airdrop")
+                .long("airdrop")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(|s| is_amount(s))
+                .help("Airdrop this many SOL to the fee payer before sending. Not on mainnet-beta"),
+        )
+        .arg(
+            Arg::new("faucet_url")
+                .long("faucet-url")
+                .value_name("URL")
+                .takes_value(true)
+                .requires("airdrop")
+                .validator(|s| Url::parse(s).map(|_| ()).map_err(|err| err.to_string()))
+                .help(
+                    "Web faucet to fall back to when the RPC node's airdrop fails. The fee payer's \
+                     address and the amount are POSTed to it as JSON: \
+                     {\"address\": ..., \"lamports\": ...}",
+                ),
+        )
+        .arg(
+            Arg::new("round_to")
+                .long("round-to")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(|s| {
+                    is_amount(s)?;
+                    if s.parse::<f64>().map_err(|err| err.to_string())? > 0. {
+                        Ok(())
+                    } else {
+                        Err("must be greater than zero".to_string())
+                    }
+                })
+                .help("Round the random amount down to a multiple of this many SOL"),
+        )
+        .arg(
+            Arg::new("min_nonzero")
+                .long("min-nonzero")
+                .value_name("LAMPORTS")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .default_missing_value("1")
+                .conflicts_with_all(&["amount", "round_to"])
+                .validator(|s| match s.parse::<u64>() {
+                    Ok(0) => Err("must be at least 1".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help(
+                    "Never pick a random amount below this many lamports, 1 if not given, so a \
+                     low balance can't make for zero-lamport transfers. With --mint, in the \
+                     token's smallest unit",
+                ),
+        )
+        .arg(
+            Arg::new("memo_template")
+                .long("memo-template")
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .help(
+                    "Add a memo to the transaction. {i} is replaced with the iteration, \
+                     {timestamp} with the current Unix time and {amount} with the amount \
+                     in lamports",
+                ),
+        )
+        .arg(
+            Arg::new("idempotency_key")
+                .long("idempotency-key")
+                .value_name("STRING")
+                .takes_value(true)
+                .conflicts_with("count")
+                .help(
+                    "Add this key to the transaction as a memo, unchanged across retries, \
+                     so downstream consumers can recognize replays of the same transfer",
+                ),
+        )
+        .arg(
+            Arg::new("compute_unit_price")
+                .long("compute-unit-price")
+                .value_name("MICRO_LAMPORTS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .conflicts_with("priority_fee_auto")
+                .help("Priority fee to pay, in micro-lamports per compute unit"),
+        )
+        .arg(
+            Arg::new("priority_fee_auto")
+                .long("priority-fee-auto")
+                .takes_value(false)
+                .help(
+                    "Set the compute unit price to the median recent prioritization fee \
+                     for the accounts in the transaction",
+                ),
+        )
+        .arg(
+            Arg::new("priority_multiplier")
+                .long("priority-multiplier")
+                .value_name("MULTIPLIER")
+                .takes_value(true)
+                .default_value("1")
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(multiplier) if multiplier.is_finite() && multiplier >= 0. => Ok(()),
+                    Ok(_) => Err("must be a finite number, zero or more".to_string()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .requires("priority_fee_auto")
+                .help("Scale the automatic compute unit price by this factor"),
+        )
+        .arg(
+            Arg::new("system_program_id")
+                .long("system-program-id")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .validator(|s| is_valid_pubkey(s))
+                .help(
+                    "Program to send the transfer instruction to, for testing a system \
+                     program deployed at a custom address [default: the system program]",
+                ),
+        )
+        .arg(
+            Arg::new("pad_to_size")
+                .long("pad-to-size")
+                .value_name("BYTES")
+                .takes_value(true)
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(size) if size <= PACKET_DATA_SIZE => Ok(()),
+                    Ok(_) => Err(format!(
+                        "the largest transaction is {PACKET_DATA_SIZE} bytes"
+                    )),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help(
+                    "Append a memo of filler so the serialized transaction is about this many \
+                     bytes, for testing how size affects fees and landing",
+                ),
+        )
+        .arg(
+            Arg::new("raw_instruction_data")
+                .long("raw-instruction-data")
+                .value_name("BASE64")
+                .takes_value(true)
+                .conflicts_with("mint")
+                .validator(|s| base64::decode(s).map(|_| ()))
+                .help(
+                    "For fuzz testing, replace the data of the transfer instruction with these \
+                     base64-encoded bytes, keeping its accounts",
+                ),
+        )
+        .arg(
+            Arg::new("measure_latency")
+                .long("measure-latency")
+                .takes_value(false)
+                .help(
+                    "Report how long the blockhash fetch, send and confirmation each took. \
+                     Confirmation is polled rather than shown with the spinner, unless \
+                     --confirm-strategy says otherwise",
+                ),
+        )
+        .arg(
+            Arg::new("metrics_file")
+                .long("metrics-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("measure_latency")
+                .help(
+                    "Keep PATH up to date with OpenMetrics histograms of the blockhash fetch, \
+                     send and confirmation latencies measured by --measure-latency, rewriting \
+                     it as each transaction is confirmed",
+                ),
+        )
+        .arg(
+            Arg::new("latency_buckets")
+                .long("latency-buckets")
+                .value_name("SECONDS,...")
+                .takes_value(true)
+                .requires("metrics_file")
+                .default_value(DEFAULT_LATENCY_BUCKETS)
+                .validator(|s| parse_buckets(s).map(|_| ()))
+                .help("Upper bounds of the --metrics-file histogram buckets, in seconds"),
+        )
+        .arg(
+            Arg::new("no_verify")
+                .long("no-verify")
+                .takes_value(false)
+                .help(
+                    "Don't verify the transaction's signatures locally before sending it, \
+                     leaving that to the RPC node",
+                ),
+        )
+        .arg(
+            Arg::new("confirm_strategy")
+                .long("confirm-strategy")
+                .value_name("STRATEGY")
+                .takes_value(true)
+                .possible_values(["spinner", "poll", "ws"])
+                .help(
+                    "How to wait for the transaction to be confirmed. \"spinner\" shows the \
+                     client's progress spinner. \"poll\" polls the signature status quietly, \
+                     and honors --tx-expiry-aware and --confirm-rpc-retries. \"ws\" waits for a \
+                     notification from --ws-url for up to --confirm-timeout. All of them honor \
+                     --confirm-commitment [default: poll with --measure-latency, \
+                     --tx-expiry-aware or --confirm-rpc-retries, ws with --ws-url, spinner \
+                     otherwise]",
+                ),
+        )
+        .arg(
+            Arg::new("ws_url")
+                .long("ws-url")
+                .value_name("URL")
+                .takes_value(true)
+                .validator(|s| Url::parse(s).map(|_| ()).map_err(|err| err.to_string()))
+                .help(
+                    "Websocket for --confirm-strategy ws [default: the websocket of the JSON RPC \
+                     URL]",
+                ),
+        )
+        .arg(
+            Arg::new("tx_expiry_aware")
+                .long("tx-expiry-aware")
+                .takes_value(false)
+                .help(
+                    "Stop waiting for confirmation as soon as the block height passes the \
+                     blockhash's last valid block height. Implies --confirm-strategy poll",
+                ),
+        )
+        .arg(
+            Arg::new("show_compute_units")
+                .long("show-compute-units")
+                .takes_value(false)
+                .help(
+                    "Fetch the confirmed transaction and report the compute units its BPF \
+                     programs logged consuming. Native programs like the system program don't \
+                     log theirs, so the figure leaves them out",
+                ),
+        )
+        .arg(
+            Arg::new("fetch_details")
+                .long("fetch-details")
+                .takes_value(false)
+                .help(
+                    "Fetch the confirmed transaction and report its instructions, \
+                     balance changes and fee",
+                ),
+        )
+        .arg(
+            Arg::new("assert_extra_unchanged")
+                .long("assert-extra-unchanged")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "message_hash",
+                ])
+                .help(
+                    "Once the transaction is confirmed, check that it didn't change the balance \
+                     of any of the extra addresses",
+                ),
+        )
+        .arg(
+            Arg::new("retry_on_any")
+                .long("retry-on-any")
+                .takes_value(false)
+                .help(
+                    "Retry the send with a fresh blockhash after transient failures such as \
+                     timeouts, connection errors and server errors",
+                ),
+        )
+        .arg(
+            Arg::new("min_confirmations")
+                .long("min-confirmations")
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .help(
+                    "After the transaction is confirmed, wait until it is N slots deep or \
+                     finalized before reporting success",
+                ),
+        )
+        .arg(
+            Arg::new("blockhash_commitment")
+                .long("blockhash-commitment")
+                .value_name("COMMITMENT")
+                .takes_value(true)
+                .possible_values(["processed", "confirmed", "finalized"])
+                .default_value("confirmed")
+                .help(
+                    "Commitment of the recent blockhash. A finalized blockhash can't be on a fork \
+                     that is abandoned, but is older, so the transaction has less time to land \
+                     before it expires. A processed blockhash is the newest but may be on a fork \
+                     that never confirms",
+                ),
+        )
+        .arg(
+            Arg::new("finalized_blockhash")
+                .long("finalized-blockhash")
+                .takes_value(false)
+                .conflicts_with("blockhash_commitment")
+                .help(
+                    "Use a finalized recent blockhash, which can't be rolled back with its slot, \
+                     whatever the confirmation commitment. The same as --blockhash-commitment \
+                     finalized",
+                ),
+        )
+        .arg(
+            Arg::new("confirm_commitment")
+                .long("confirm-commitment")
+                .value_name("COMMITMENT")
+                .takes_value(true)
+                .possible_values(["processed", "confirmed", "finalized"])
+                .default_value("confirmed")
+                .help(
+                    "Commitment the transaction must reach to count as confirmed, which also \
+                     applies to the balances read along the way. Processed gives the quickest \
+                     feedback but the transaction may still be dropped with its fork, while \
+                     finalized takes the longest but can't be rolled back",
+                ),
+        )
+        .arg(
+            Arg::new("preflight_commitment")
+                .long("preflight-commitment")
+                .value_name("COMMITMENT")
+                .takes_value(true)
+                .possible_values(["processed", "confirmed", "finalized"])
+                .help(
+                    "Commitment of the bank the node simulates the transaction against before \
+                     accepting it. Processed is the quickest to reflect recent transactions, \
+                     finalized the least likely to be rolled back [default: \
+                     --confirm-commitment]",
+                ),
+        )
+        .arg(
+            Arg::new("confirm_rpc_retries")
+                .long("confirm-rpc-retries")
+                .value_name("N")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable::<u32>)
+                .help(
+                    "Keep waiting for confirmation through up to N failed status queries in a \
+                     row, rather than giving up on the first. Implies --confirm-strategy poll",
+                ),
+        )
+        .arg(
+            Arg::new("confirm_timeout")
+                .long("confirm-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("60")
+                .validator(is_parsable::<u64>)
+                .help(
+                    "How long to wait for the transaction to be confirmed with --escalate-fee \
+                     or --confirm-strategy ws, or to reach --min-confirmations",
+                ),
+        )
+        .arg(
+            Arg::new("max_fee")
+                .long("max-fee")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(|s| is_amount(s))
+                .help(
+                    "Don't send a transaction whose fee is over this many SOL. With --count, \
+                     the transaction is skipped and the run carries on",
+                ),
+        )
+        .arg(
+            Arg::new("escalate_fee")
+                .long("escalate-fee")
+                .takes_value(false)
+                .requires("max_fee")
+                .conflicts_with_all(&["add_signature", "blockhash"])
+                .help(
+                    "When the transaction isn't confirmed within --confirm-timeout, re-sign it \
+                     with a fresh blockhash and a compute unit price raised by \
+                     --escalation-factor, for as long as the fee stays within --max-fee",
+                ),
+        )
+        .arg(
+            Arg::new("escalation_factor")
+                .long("escalation-factor")
+                .value_name("FACTOR")
+                .takes_value(true)
+                .default_value("2")
+                .requires("escalate_fee")
+                .validator(|s| match s.parse::<f64>() {
+                    Ok(factor) if factor > 1. => Ok(()),
+                    Ok(_) => Err("must be greater than 1".to_string()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help("How much --escalate-fee multiplies the compute unit price by each time"),
+        )
+        .arg(
+            Arg::new("skip_underfunded")
+                .long("skip-underfunded")
+                .takes_value(false)
+                .requires("count")
+                .conflicts_with_all(&[
+                    "estimate",
+                    "plan",
+                ])
+                .help(
+                    "With --count, skip the transactions the fee payer's balance can't cover and \
+                     carry on with the rest, counting them as skipped",
+                ),
+        )
+        .arg(
+            Arg::new("fail_fast")
+                .long("fail-fast")
+                .takes_value(false)
+                .requires("count")
+                .help(
+                    "With --count, exit with an error at the first transaction that fails or \
+                     is skipped, without a summary. Without this or --stop-on-failure, every \
+                     transaction is attempted, and the exit status is non-zero if any failed",
+                ),
+        )
+        .arg(
+            Arg::new("stop_on_failure")
+                .long("stop-on-failure")
+                .takes_value(false)
+                .requires("count")
+                .conflicts_with("fail_fast")
+                .help(
+                    "With --count, start no more transactions after the first that fails or is \
+                     skipped, but still print the summary. The exit status is then non-zero",
+                ),
+        )
+        .arg(
+            Arg::new("sequential_confirm")
+                .long("sequential-confirm")
+                .takes_value(false)
+                .requires("count")
+                .conflicts_with_all(&[
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "message_hash",
+                ])
+                .help(
+                    "With --count, for transfers that depend on each other, require each \
+                     transaction to be confirmed before the next one starts. Every --count run \
+                     already sends one transaction at a time, so this makes that explicit and, \
+                     with --verbose, reports each wait",
+                ),
+        )
+        .arg(
+            Arg::new("max_retries")
+                .long("max-retries")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("3")
+                .validator(is_parsable::<u32>)
+                .requires("retry_on_any")
+                .help("Maximum number of retries"),
+        )
+        .arg(
+            Arg::new("retry_budget")
+                .long("retry-budget")
+                .value_name("COUNT")
+                .takes_value(true)
+                .validator(is_parsable::<u32>)
+                .requires_all(&["retry_on_any", "count"])
+                .help(
+                    "Most retries to make across all the transactions of the run. Once they are \
+                     used up, a transaction that fails is not retried",
+                ),
+        )
+        .arg(
+            Arg::new("backoff_ms")
+                .long("backoff-ms")
+                .value_name("MILLISECONDS")
+                .takes_value(true)
+                .default_value("500")
+                .validator(is_parsable::<u64>)
+                .requires("retry_on_any")
+                .help(
+                    "Backoff before the first retry, doubling after each subsequent retry up to \
+                     60 seconds. See --retry-jitter",
+                ),
+        )
+        .arg(
+            Arg::new("retry_jitter")
+                .long("retry-jitter")
+                .value_name("JITTER")
+                .takes_value(true)
+                .possible_values(["full", "none"])
+                .default_value("full")
+                .requires("retry_on_any")
+                .help(
+                    "\"full\" waits a random part of each retry's backoff, drawn from the --seed \
+                     random number generator, so transactions failing together don't all retry \
+                     together. \"none\" always waits the whole backoff",
+                ),
+        )
+        .arg(
+            Arg::new("signatures_only")
+                .long("signatures-only")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "output",
+                    "dump_transaction",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "validate_only",
+                    "raw_transaction",
+                    "show_compute_units",
+                    "fetch_details",
+                ])
+                .help(
+                    "Print nothing on stdout but the signature of each transaction as it is \
+                     confirmed, one per line",
+                ),
+        )
+        .arg(
+            Arg::new("template")
+                .long("template")
+                .value_name("TEMPLATE")
+                .takes_value(true)
+                .validator(is_output_template)
+                .conflicts_with_all(&[
+                    "signatures_only",
+                    "mint",
+                    "dry_run",
+                    "estimate",
+                    "plan",
+                    "show_compute_units",
+                    "fetch_details",
+                ])
+                .help(
+                    "Print TEMPLATE on stdout for each confirmed transaction instead of the usual \
+                     output. {signature}, {slot}, {fee}, {recipient}, {amount_sol} and \
+                     {amount_lamports} are replaced with those of the transaction, the fee in \
+                     lamports",
+                ),
+        )
+        .arg(
+            Arg::new("ndjson")
+                .long("ndjson")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "Write one JSON object per transaction to PATH, or to stdout if PATH is -, \
+                     as soon as it has been confirmed or has failed. Writing to stdout requires \
+                     --output json",
+                ),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with_all(&[
+                    "count",
+                    "to",
+                    "to_pda",
+                    "amount",
+                    "round_to",
+                    "memo_template",
+                    "idempotency_key",
+                    "extra_addresses",
+                    "message_hash",
+                    "add_signature",
+                ])
+                .help(
+                    "Send the same transfers as an earlier run's --ndjson output at PATH, from the \
+                     same fee payers to the same recipients, in order and with fresh blockhashes. \
+                     Transactions that failed are left out",
+                ),
+        )
+        .arg(
+            Arg::new("replay_failures")
+                .long("replay-failures")
+                .takes_value(false)
+                .requires("replay")
+                .help("With --replay, also send the transactions that failed"),
+        )
+        .arg(
+            Arg::new("summary_csv")
+                .long("summary-csv")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "Write a CSV file with a row per transaction: index, signature, recipient, \
+                     lamports, fee, slot, status and error",
+                ),
+        )
+        .arg(
+            Arg::new("cost_basis")
+                .long("cost-basis")
+                .takes_value(false)
+                .help(
+                    "After the run, report for each recipient what the confirmed transactions \
+                     transferred, paid in fees and took from the fee payer in all",
+                ),
+        )
+        .arg(
+            Arg::new("dump_failed")
+                .long("dump-failed")
+                .value_name("DIR")
+                .takes_value(true)
+                .help(
+                    "Write each transaction that fails to send or confirm, signed and \
+                     base64-encoded, to a file in DIR along with its error. Resend one with \
+                     --raw-transaction",
+                ),
+        )
+        .arg(
+            Arg::new("result_socket")
+                .long("result-socket")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "Stream the same records as --ndjson to the Unix domain socket or named pipe \
+                     at PATH, for a local collector. Sending carries on without it if PATH can't \
+                     be opened or written to",
+                ),
+        )
+        .arg(
+            Arg::new("extra_from_program")
+                .long("extra-from-program")
+                .value_name("PROGRAM_ID")
+                .takes_value(true)
+                .validator(|s| is_valid_pubkey(s))
+                .help(
+                    "Also append the addresses of accounts owned by this program, up to \
+                     --extra-limit of them",
+                ),
+        )
+        .arg(
+            Arg::new("extra_limit")
+                .long("extra-limit")
+                .value_name("N")
+                .takes_value(true)
+                .default_value("8")
+                .requires("extra_from_program")
+                .validator(is_parsable::<usize>)
+                .help("Most addresses to take from --extra-from-program"),
+        )
+        .arg(
+            Arg::new("extra_data_size")
+                .long("extra-data-size")
+                .visible_alias("extra-account-size")
+                .value_name("BYTES")
+                .takes_value(true)
+                .requires("extra_from_program")
+                .validator(is_parsable::<u64>)
+                .help(
+                    "Only take --extra-from-program accounts holding exactly this many bytes of \
+                     data. Programs with many accounts can be too slow to list without it",
+                ),
+        )
+        .arg(
+            Arg::new("extra_addresses")
+                .value_name("ADDRESS")
+                .validator(|s| is_valid_pubkey(s))
+                .takes_value(true)
+                .multiple(true)
+                .help("Extra addresses to append"),
+        )
+}
+
+pub async fn run(
+    context: &Context,
+    matches: &ArgMatches,
+    wallet_manager: &mut Option<Arc<RemoteWalletManager>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let verbose = context.verbose;
+    let output_format = context.output_format;
+    let json_rpc_url = &context.json_rpc_urls[0];
+
+    // Global arguments given before the subcommand aren't covered by clap's conflict checks
+    if matches.is_present("signatures_only") && (verbose || output_format != OutputFormat::Text) {
+        return Err("error: --signatures-only can't be used with --verbose or --output".into());
+    }
+    // The records share stdout with everything else, which must be JSON too to keep it parseable
+    if matches.value_of("ndjson") == Some("-") && (verbose || output_format != OutputFormat::Json) {
+        return Err(
+            "error: --ndjson - requires --output json, and can't be used with --verbose".into(),
+        );
+    }
+    if matches.is_present("template") && output_format != OutputFormat::Text {
+        return Err("error: --template can't be used with --output".into());
+    }
+    if matches.is_present("skip_balance_check")
+        && !["amount", "amount_file", "usd", "replay"]
+            .iter()
+            .any(|name| matches.is_present(name))
+    {
+        return Err(
+            "error: --skip-balance-check requires --amount, --amount-file, --usd or --replay"
+                .into(),
+        );
+    }
+
+    if let (true, Some(compare_url)) = (context.strict_url, matches.value_of("compare_url")) {
+        check_strict_url(&Url::parse(compare_url)?)?;
+    }
+
+    if let Some(raw_transaction) = matches.value_of("raw_transaction") {
+        return send_raw_transaction(&context.rpc_client(), raw_transaction, output_format).await;
+    }
+
+    let external_signatures = matches
+        .values_of("add_signature")
+        .into_iter()
+        .flatten()
+        .map(parse_pubkey_signature)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (signers, keypair_paths) = match (
+        matches.value_of("keypair_dir"),
+        pubkey_of(matches, "estimate_for"),
+    ) {
+        (Some(keypair_dir), _) => {
+            // Global arguments given before the subcommand aren't covered by clap's conflict
+            // checks
+            if ["keypair", "mnemonic_stdin", "keypair_base58_stdin"]
+                .iter()
+                .any(|name| matches.is_present(name))
+            {
+                return Err("error: --keypair-dir can't be used with another signer".into());
+            }
+            read_keypair_dir(keypair_dir)?
+                .into_iter()
+                .map(|(keypair, path)| (Box::new(keypair) as Box<dyn Signer>, path))
+                .unzip()
+        }
+        (None, Some(feepayer_address)) => {
+            // Global arguments given before the subcommand aren't covered by clap's conflict
+            // checks
+            if ["keypair", "mnemonic_stdin", "keypair_base58_stdin"]
+                .iter()
+                .any(|name| matches.is_present(name))
+            {
+                return Err("error: --estimate-for can't be used with another signer".into());
+            }
+            (
+                vec![Box::new(NullSigner::new(&feepayer_address)) as Box<dyn Signer>],
+                vec![],
+            )
+        }
+        (None, None) => {
+            // With an external signer, only the fee payer's address is needed
+            let default_signer = context.signer(
+                matches,
+                wallet_manager,
+                matches.is_present("message_hash")
+                    || matches.is_present("emit_instructions")
+                    || !external_signatures.is_empty(),
+            )?;
+            (vec![default_signer], vec![])
+        }
+    };
+    let default_signer = &signers[0];
+    let cosigners = match matches.value_of("keypairs_file") {
+        Some(path) => read_keypairs_file(path)?,
+        None => vec![],
+    };
+
+    if matches.is_present("check_signers") {
+        let all_signers = signers
+            .iter()
+            .map(|signer| signer.as_ref())
+            .chain(cosigners.iter().map(|cosigner| cosigner as &dyn Signer))
+            .collect::<Vec<_>>();
+        check_signers(&all_signers, verbose)?;
+    }
+
+    let mut extra_addresses = pubkeys_of(matches, "extra_addresses").unwrap_or_default();
+
+    let raw_instruction_data = matches
+        .value_of("raw_instruction_data")
+        .map(base64::decode)
+        .transpose()?;
+    if let Some(raw_instruction_data) = &raw_instruction_data {
+        eprintln!(
+            "warning: --raw-instruction-data is for fuzz testing. The transfer instruction's data \
+             is replaced with these {} bytes, so the transaction may be rejected, or do \
+             something other than a transfer",
+            raw_instruction_data.len()
+        );
+    }
+
+    // Files are read before --validate-only exits, so that it checks them too
+    let replay = match matches.value_of("replay") {
+        Some(path) => Some(read_replay(
+            path,
+            matches.is_present("replay_failures"),
+            &signers
+                .iter()
+                .map(|signer| signer.pubkey())
+                .collect::<Vec<_>>(),
+        )?),
+        None => None,
+    };
+    let file_amount = matches
+        .value_of("amount_file")
+        .map(read_amount_file)
+        .transpose()?;
+    // As are the --pda-seed seeds
+    let to = match pubkey_of(matches, "to_pda") {
+        Some(program_id) => {
+            let seeds = matches
+                .values_of("pda_seed")
+                .into_iter()
+                .flatten()
+                .map(base64::decode)
+                .collect::<Result<Vec<_>, _>>()?;
+            let (pda, bump) = find_pda(&program_id, &seeds)?;
+            eprintln!("Program derived address: {pda} (bump {bump})");
+            Some(pda)
+        }
+        None => pubkey_of(matches, "to"),
+    };
+
+    if matches.is_present("validate_only") {
+        if verbose {
+            println!("JSON RPC URL: {}", context.json_rpc_urls.join(", "));
+            println!("Fee payer: {}", default_signer.pubkey());
+            println!("Extra addresses: {extra_addresses:?}");
+        }
+        println!("Arguments are valid");
+        return Ok(());
+    }
+
+    if verbose {
+        println!("JSON RPC URL: {}", context.json_rpc_urls.join(", "));
+        // Header values are often credentials, so only their names are shown
+        for name in context.rpc_headers.keys() {
+            println!("RPC header: {name}: <redacted>");
+        }
+        match context
+            .rpc_client()
+            .call("getEpochInfo", |rpc_client| rpc_client.get_epoch_info())
+            .await
+        {
+            Ok(epoch_info) => println!(
+                "Epoch: {}, slot: {}, block height: {}",
+                epoch_info.epoch, epoch_info.absolute_slot, epoch_info.block_height
+            ),
+            Err(err) => eprintln!("warning: unable to get epoch info: {err}"),
+        }
+    }
+
+    let count = match &replay {
+        Some(transfers) => transfers.len(),
+        None => value_of::<usize>(matches, "count").unwrap(),
+    };
+    let warmup = value_of::<usize>(matches, "warmup").unwrap_or_default();
+    if warmup > 0 && count == 1 && !matches.is_present("measure_latency") {
+        return Err("error: --warmup requires --measure-latency or a --count over 1".into());
+    }
+    // Polling is implied by the options only it honors, and the websocket by its URL
+    let poll_only = ["tx_expiry_aware", "confirm_rpc_retries"]
+        .into_iter()
+        .filter(|name| matches.occurrences_of(name) > 0)
+        .collect::<Vec<_>>();
+    let confirm_strategy = match matches
+        .value_of("confirm_strategy")
+        .map(ConfirmStrategy::from_str)
+    {
+        Some(confirm_strategy) => confirm_strategy,
+        None if !poll_only.is_empty() || matches.is_present("measure_latency") => {
+            ConfirmStrategy::Poll
+        }
+        None if matches.is_present("ws_url") => ConfirmStrategy::Ws,
+        None => ConfirmStrategy::Spinner,
+    };
+    if confirm_strategy != ConfirmStrategy::Poll && !poll_only.is_empty() {
+        return Err(format!(
+            "error: --{} only applies to --confirm-strategy poll",
+            poll_only[0].replace('_', "-")
+        )
+        .into());
+    }
+    let plan = matches.is_present("plan");
+    let sequential_confirm = matches.is_present("sequential_confirm");
+    let fail_fast = matches.is_present("fail_fast");
+    let stop_on_failure = matches.is_present("stop_on_failure");
+    let ramp = match (
+        value_of::<f64>(matches, "ramp_from"),
+        value_of::<f64>(matches, "ramp_to"),
+        value_of::<u64>(matches, "ramp_duration"),
+    ) {
+        (Some(from), Some(to), Some(duration)) => Some(Ramp {
+            from,
+            to,
+            duration: Duration::from_secs(duration),
+        }),
+        _ => None,
+    };
+    let mut rate_limiter = match &ramp {
+        Some(ramp) => Some(RateLimiter::new(ramp.from)),
+        None => value_of::<f64>(matches, "target_tps").map(RateLimiter::new),
+    };
+    let mut send_starts = vec![];
+    let spread_over = value_of::<f64>(matches, "spread_over").map(Duration::from_secs_f64);
+
+    let confirm_commitment = value_of::<CommitmentConfig>(matches, "confirm_commitment").unwrap();
+    let rpc_client = context.rpc_client_with_commitment(confirm_commitment);
+    let token_mint = match pubkey_of(matches, "mint") {
+        Some(mint) => Some(get_token_mint(&rpc_client, mint).await?),
+        None => None,
+    };
+    if let Some(program_id) = pubkey_of(matches, "extra_from_program") {
+        let program_addresses = get_program_addresses(
+            &rpc_client,
+            &program_id,
+            value_of(matches, "extra_data_size"),
+            value_of(matches, "extra_limit").unwrap(),
+            verbose,
+        )
+        .await?;
+        if verbose {
+            println!("Extra addresses from {program_id}: {program_addresses:?}");
+        }
+        extra_addresses.extend(program_addresses);
+    }
+    // With --mint, --amount and --amount-cap are in the mint's tokens
+    let amount_of = |name| match &token_mint {
+        Some(token_mint) => value_of::<f64>(matches, name)
+            .map(|amount| spl_token::ui_amount_to_amount(amount, token_mint.decimals)),
+        None => lamports_of_sol(matches, name),
+    };
+    let amount = match (value_of::<f64>(matches, "usd"), file_amount) {
+        (Some(usd), _) => {
+            Some(usd_to_lamports(matches.value_of("price_source").unwrap(), usd).await?)
+        }
+        (None, Some(file_amount)) => Some(file_amount.to_units(token_mint.as_ref())),
+        (None, None) => amount_of("amount"),
+    };
+    let amount_cap = amount_of("amount_cap");
+    let blockhash = match matches.value_of("blockhash") {
+        Some(blockhash) => Some(match blockhash.parse::<Hash>() {
+            Ok(blockhash) => (blockhash, None),
+            Err(_) => {
+                let stored =
+                    read_blockhash_file(blockhash).map_err(|err| format!("error: {err}"))?;
+                if stored.may_have_expired() {
+                    eprintln!(
+                        "warning: the blockhash in {blockhash} was fetched {}s ago, so it may \
+                         have expired",
+                        stored.age.as_secs()
+                    );
+                }
+                (stored.blockhash, Some(stored.last_valid_block_height))
+            }
+        }),
+        None => None,
+    };
+
+    let blockhash_commitment = if matches.is_present("finalized_blockhash") {
+        if verbose {
+            println!(
+                "Using a finalized blockhash, which is older and so leaves the transaction less \
+                 time to land before it expires"
+            );
+        }
+        CommitmentConfig::finalized()
+    } else {
+        value_of(matches, "blockhash_commitment").unwrap()
+    };
+
+    let config = SendConfig {
+        rpc_client,
+        json_rpc_url,
+        feepayer_address: default_signer.pubkey(),
+        signers,
+        keypair_paths,
+        cosigners,
+        extra_addresses,
+        system_program_id: pubkey_of(matches, "system_program_id")
+            .unwrap_or_else(system_program::id),
+        raw_instruction_data,
+        pad_to_size: value_of(matches, "pad_to_size"),
+        compute_unit_price: value_of::<u64>(matches, "compute_unit_price"),
+        priority_fee_auto: matches.is_present("priority_fee_auto"),
+        priority_multiplier: value_of::<f64>(matches, "priority_multiplier").unwrap(),
+        round_to: lamports_of_sol(matches, "round_to"),
+        min_nonzero: value_of(matches, "min_nonzero"),
+        memo_template: matches.value_of("memo_template"),
+        idempotency_key: matches.value_of("idempotency_key"),
+        dry_run: matches.is_present("dry_run"),
+        fail_log_patterns: matches
+            .values_of("fail_if_log_contains")
+            .into_iter()
+            .flatten()
+            .collect(),
+        assert_effect: matches.is_present("assert_effect"),
+        max_compute_units: value_of(matches, "max_compute_units"),
+        // A plan is an estimate that lists every transfer
+        estimate: matches.is_present("estimate") || matches.is_present("estimate_for") || plan,
+        estimate_for: matches.is_present("estimate_for"),
+        dump_transaction: matches.is_present("dump_transaction"),
+        message_hash: matches.is_present("message_hash"),
+        sign_only: matches.is_present("sign_only"),
+        emit_instructions: matches.is_present("emit_instructions"),
+        diff_flag: matches.value_of("diff_flag"),
+        external_signatures,
+        amount,
+        amount_cap,
+        max_fraction: value_of::<f64>(matches, "max_fraction").unwrap(),
+        blockhash,
+        blockhash_commitment,
+        measure_latency: matches.is_present("measure_latency"),
+        confirm_strategy,
+        confirm_commitment,
+        ws_url: matches
+            .value_of("ws_url")
+            .map(|ws_url| ws_url.to_string())
+            .unwrap_or_else(|| solana_cli_config::Config::compute_websocket_url(json_rpc_url)),
+        confirm_rpc_retries: value_of(matches, "confirm_rpc_retries").unwrap(),
+        preflight_commitment: value_of(matches, "preflight_commitment"),
+        verify: !matches.is_present("no_verify"),
+        show_compute_units: matches.is_present("show_compute_units"),
+        fetch_details: matches.is_present("fetch_details"),
+        assert_extra_unchanged: matches.is_present("assert_extra_unchanged"),
+        skip_underfunded: matches.is_present("skip_underfunded"),
+        skip_balance_check: matches.is_present("skip_balance_check"),
+        summary_csv: matches.is_present("summary_csv"),
+        cost_basis: matches.is_present("cost_basis"),
+        dump_failed: matches.value_of("dump_failed").map(Path::new),
+        retry_on_any: matches.is_present("retry_on_any"),
+        max_retries: value_of::<u32>(matches, "max_retries").unwrap(),
+        retry_budget: value_of(matches, "retry_budget"),
+        retries_used: AtomicU32::new(0),
+        max_fee: lamports_of_sol(matches, "max_fee"),
+        escalate_fee: matches.is_present("escalate_fee"),
+        escalation_factor: value_of::<f64>(matches, "escalation_factor").unwrap(),
+        min_confirmations: value_of::<usize>(matches, "min_confirmations"),
+        confirm_timeout: Duration::from_secs(value_of(matches, "confirm_timeout").unwrap()),
+        backoff: Duration::from_millis(value_of(matches, "backoff_ms").unwrap()),
+        retry_jitter: matches.value_of("retry_jitter") == Some("full"),
+        output_format,
+        signatures_only: matches.is_present("signatures_only"),
+        template: matches.value_of("template"),
+        verbose,
+        open: matches.is_present("open"),
+        to,
+        token_mint,
+        create_ata: matches.is_present("create_ata"),
+        compare: matches.value_of("compare_url").map(|compare_url| {
+            (
+                compare_url,
+                FailoverRpcClient::new(
+                    &[compare_url.to_string()],
+                    &context.rpc_headers,
+                    context.rpc_limit.clone(),
+                    confirm_commitment,
+                    verbose,
+                ),
+            )
+        }),
+    };
+
+    // Sending to someone else on the wrong cluster is hard to undo, so say which cluster it is
+    let recipient = config.recipient(&config.feepayer_address);
+    if recipient != config.feepayer_address
+        && !(config.dry_run
+            || config.estimate
+            || config.message_hash
+            || config.sign_only
+            || config.emit_instructions
+            || config.diff_flag.is_some())
+    {
+        eprintln!(
+            "Sending to {recipient} on {} ({json_rpc_url})",
+            cluster_name(json_rpc_url)
+        );
+    }
+
+    let wait_timeout = Duration::from_secs(value_of(matches, "wait_timeout").unwrap());
+    if matches.is_present("health_check") {
+        // The reference is likely another provider, which shouldn't get this one's credentials
+        let reference = matches.value_of("reference_url").map(|reference_url| {
+            let reference_url = normalize_to_url_if_moniker(reference_url);
+            (
+                reference_url.clone(),
+                FailoverRpcClient::new(
+                    &[reference_url],
+                    &HeaderMap::new(),
+                    context.rpc_limit.clone(),
+                    CommitmentConfig::confirmed(),
+                    verbose,
+                ),
+            )
+        });
+        health_check(
+            &config.rpc_client,
+            reference
+                .as_ref()
+                .map(|(reference_url, reference)| (reference_url.as_str(), reference)),
+            value_of(matches, "max_slot_lag").unwrap(),
+            verbose,
+        )
+        .await?;
+    }
+
+    if let Some(lamports) = lamports_of_sol(matches, "airdrop") {
+        airdrop(
+            &config.rpc_client,
+            &config.feepayer_address,
+            lamports,
+            matches.value_of("faucet_url"),
+            wait_timeout,
+            verbose,
+        )
+        .await?;
+    }
+
+    if let Some(min_balance) = lamports_of_sol(matches, "wait_for_balance") {
+        wait_for_balance(
+            &config.rpc_client,
+            &config.feepayer_address,
+            min_balance,
+            wait_timeout,
+            verbose,
+        )
+        .await?;
+    }
+
+    if let Some(dir) = config.dump_failed {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("error: unable to create {}: {err}", dir.display()))?;
+    }
+
+    let mut ndjson: Option<Box<dyn Write>> = match matches.value_of("ndjson") {
+        Some("-") => Some(Box::new(std::io::stdout())),
+        Some(path) => {
+            Some(Box::new(File::create(path).map_err(|err| {
+                format!("error: unable to create {path}: {err}")
+            })?))
+        }
+        None => None,
+    };
+    let mut summary_csv = match matches.value_of("summary_csv") {
+        Some(path) => {
+            let mut summary_csv = File::create(path)
+                .map_err(|err| format!("error: unable to create {path}: {err}"))?;
+            write_csv_row(&mut summary_csv, &SUMMARY_CSV_HEADER)
+                .map_err(|err| format!("error: unable to write {path}: {err}"))?;
+            Some(summary_csv)
+        }
+        None => None,
+    };
+    let mut result_socket =
+        matches
+            .value_of("result_socket")
+            .and_then(|path| match open_result_socket(path) {
+                Ok(result_socket) => Some(result_socket),
+                Err(err) => {
+                    eprintln!("warning: unable to open result socket {path}: {err}");
+                    None
+                }
+            });
+
+    let mut metrics = match matches.value_of("metrics_file") {
+        Some(path) => {
+            let metrics = LatencyMetrics::new(&parse_buckets(
+                matches.value_of("latency_buckets").unwrap(),
+            )?);
+            metrics
+                .write_file(Path::new(path))
+                .map_err(|err| format!("error: unable to write {path}: {err}"))?;
+            Some((Path::new(path), metrics))
+        }
+        None => None,
+    };
+
+    let mut rng = match value_of::<u64>(matches, "seed") {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    if let Some(diff_flag) = config.diff_flag {
+        let transfer = plan_transfer(&config, 0, &mut rng).await?;
+        return diff_transfer(&config, diff_flag, transfer, &mut rng).await;
+    }
+
+    let mut estimate: Option<Estimate> = None;
+    let mut planned_transfers = vec![];
+    let mut latencies = vec![];
+    let mut succeeded = 0;
+    let mut failed = 0;
+    let mut skipped = 0;
+    // With --stop-on-failure, how many transactions were never started
+    let mut not_started = None;
+    let mut cost_basis = BTreeMap::<Pubkey, CostBasis>::new();
+
+    // Warmup transactions are kept out of everything that follows, down to the retry budget
+    for iteration in 0..warmup {
+        let outcome = match plan_transfer(&config, iteration, &mut rng).await {
+            Ok(transfer) => send_one(&config, &transfer, &mut rng, true).await,
+            Err(err) => Err(err),
+        };
+        match outcome {
+            Ok(Outcome::Sent(result)) => {
+                if verbose {
+                    println!(
+                        "Warmup transaction {} of {warmup}: {}",
+                        iteration + 1,
+                        result.signature
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(err) => eprintln!(
+                "warning: warmup transaction {} of {warmup} failed: {err}",
+                iteration + 1
+            ),
+        }
+    }
+    config.retries_used.store(0, Ordering::Relaxed);
+
+    let start = Instant::now();
+
+    for iteration in 0..count {
+        if sequential_confirm && verbose && iteration + 1 < count {
+            println!(
+                "Transaction {} will be confirmed before transaction {} starts",
+                iteration + 1,
+                iteration + 2
+            );
+        }
+        if let Some(rate_limiter) = rate_limiter.as_mut() {
+            if let Some(ramp) = &ramp {
+                rate_limiter.set_rate(ramp.rate_at(start.elapsed()));
+            }
+            rate_limiter.acquire().await;
+        }
+        // Each transaction has its own slot in the window, so a slow one eats into the wait for
+        // the next rather than pushing back all the rest
+        if let Some(spread_over) = spread_over {
+            let slot = spread_over.mul_f64(iteration as f64 / count as f64);
+            tokio::time::sleep_until((start + slot).into()).await;
+        }
+        if ramp.is_some() {
+            send_starts.push(start.elapsed());
+        }
+
+        let transfer = match &replay {
+            Some(transfers) => Ok(Transfer {
+                lamports: config.cap_amount(transfers[iteration].lamports),
+                ..transfers[iteration].clone()
+            }),
+            None => plan_transfer(&config, iteration, &mut rng).await,
+        };
+        let (transfer, outcome) = match transfer {
+            Ok(transfer) => {
+                let outcome = send_one(&config, &transfer, &mut rng, false).await;
+                (Some(transfer), outcome)
+            }
+            Err(err) => (None, Err(err)),
+        };
+        if ndjson.is_some() || result_socket.is_some() || summary_csv.is_some() {
+            let fee_payer = transfer
+                .as_ref()
+                .map(|transfer| config.signers[transfer.signer].pubkey());
+            let record = match &outcome {
+                Ok(Outcome::Sent(result)) => NdjsonRecord {
+                    status: "confirmed",
+                    result: Some(result),
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Ok(Outcome::Simulated | Outcome::Built(_)) => NdjsonRecord {
+                    status: "simulated",
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Ok(Outcome::MessagePrinted) => NdjsonRecord {
+                    status: "printed",
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Ok(Outcome::Compared) => NdjsonRecord {
+                    status: "compared",
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Ok(Outcome::FeeCapped { fee }) => NdjsonRecord {
+                    status: "skipped",
+                    fee_lamports: Some(*fee),
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Ok(Outcome::Underfunded { .. }) => NdjsonRecord {
+                    status: "skipped",
+                    error: Some("fee payer balance too low".to_string()),
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Ok(Outcome::Estimated { fee, .. }) => NdjsonRecord {
+                    status: "estimated",
+                    fee_lamports: Some(*fee),
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+                Err(err) => NdjsonRecord {
+                    status: "failed",
+                    error: Some(err.to_string()),
+                    ..NdjsonRecord::new(iteration, transfer.as_ref(), fee_payer.as_ref())
+                },
+            };
+            if let Some(summary_csv) = summary_csv.as_mut() {
+                record
+                    .write_csv(
+                        summary_csv,
+                        &transfer.as_ref().map_or_else(
+                            || config.recipient(&config.feepayer_address),
+                            |transfer| transfer.recipient,
+                        ),
+                    )
+                    .map_err(|err| format!("error: unable to write CSV summary: {err}"))?;
+            }
+            let line = serde_json::to_string(&record)?;
+            if let Some(ndjson) = ndjson.as_mut() {
+                writeln!(ndjson, "{line}")
+                    .and_then(|()| ndjson.flush())
+                    .map_err(|err| format!("error: unable to write NDJSON output: {err}"))?;
+            }
+            if let Some(socket) = result_socket.as_mut() {
+                if let Err(err) = writeln!(socket, "{line}").and_then(|()| socket.flush()) {
+                    eprintln!(
+                        "warning: unable to write to result socket, no longer using it: {err}"
+                    );
+                    result_socket = None;
+                }
+            }
+        }
+
+        match outcome {
+            Ok(Outcome::Sent(result)) => {
+                succeeded += 1;
+                if let Some(latency) = &result.latency {
+                    latencies.push(latency.total_ms);
+                    if let Some((path, metrics)) = metrics.as_mut() {
+                        metrics.observe(
+                            latency.blockhash_ms,
+                            latency.send_ms,
+                            latency.confirmation_ms,
+                        );
+                        if let Err(err) = metrics.write_file(path) {
+                            eprintln!("warning: unable to write {}: {err}", path.display());
+                        }
+                    }
+                }
+                if let (true, Some(transfer)) = (config.cost_basis, &transfer) {
+                    let recipient = transfer.recipient;
+                    let cost_basis = cost_basis
+                        .entry(recipient)
+                        .or_insert_with(|| CostBasis::new(recipient.to_string()));
+                    cost_basis.transactions += 1;
+                    cost_basis.transferred =
+                        cost_basis.transferred.saturating_add(transfer.lamports);
+                    // The fee payer is the first account
+                    if let Some(details) = &result.details {
+                        cost_basis.fee_lamports =
+                            cost_basis.fee_lamports.saturating_add(details.fee_lamports);
+                        if let Some(balance) = details.balances.first() {
+                            cost_basis.spent_lamports = cost_basis.spent_lamports.saturating_add(
+                                balance.pre_lamports.saturating_sub(balance.post_lamports),
+                            );
+                        }
+                    }
+                }
+            }
+            Ok(
+                Outcome::Simulated
+                | Outcome::MessagePrinted
+                | Outcome::Compared
+                | Outcome::Built(_),
+            ) => succeeded += 1,
+            Ok(Outcome::Estimated {
+                lamports,
+                fee,
+                balance,
+            }) => {
+                let estimate = estimate.get_or_insert(Estimate {
+                    transactions: 0,
+                    total_lamports: 0,
+                    total_fee_lamports: 0,
+                    total_required_lamports: 0,
+                    balance_lamports: balance,
+                    sufficient: false,
+                });
+                estimate.transactions += 1;
+                estimate.total_lamports = estimate.total_lamports.saturating_add(lamports);
+                estimate.total_fee_lamports = estimate.total_fee_lamports.saturating_add(fee);
+                estimate.total_required_lamports = estimate
+                    .total_lamports
+                    .saturating_add(estimate.total_fee_lamports);
+                estimate.sufficient = estimate.balance_lamports >= estimate.total_required_lamports;
+                if let (true, Some(transfer)) = (plan, &transfer) {
+                    planned_transfers.push(PlannedTransfer {
+                        recipient: transfer.recipient.to_string(),
+                        lamports,
+                        fee_lamports: fee,
+                    });
+                }
+            }
+            Ok(Outcome::FeeCapped { fee }) => {
+                let message = format!(
+                    "error: fee of {} is over --max-fee {}, not sending",
+                    Sol(fee),
+                    Sol(config.max_fee.unwrap_or_default())
+                );
+                if count == 1 || fail_fast {
+                    return Err(message.into());
+                }
+                skipped += 1;
+                eprintln!("{message}");
+            }
+            Ok(Outcome::Underfunded { required, balance }) => {
+                let message = format!(
+                    "error: fee payer balance of {} can't cover the {} needed, not sending",
+                    Sol(balance),
+                    Sol(required)
+                );
+                if count == 1 || fail_fast {
+                    return Err(message.into());
+                }
+                skipped += 1;
+                eprintln!("{message}");
+            }
+            Err(err) if count == 1 || fail_fast => return Err(err),
+            Err(err) => {
+                failed += 1;
+                eprintln!("{err}");
+            }
+        }
+        // Every transaction is confirmed before the next starts, so all that's left to stop is
+        // the ones not yet started
+        if stop_on_failure && (failed > 0 || skipped > 0) {
+            not_started = Some(count - iteration - 1);
+            break;
+        }
+    }
+
+    if let Some(estimate) = estimate {
+        match output_format {
+            OutputFormat::Text => {
+                if plan {
+                    println!(
+                        "{:<5}  {:<44}  {:>16}  {:>16}",
+                        "#", "Recipient", "Amount", "Fee"
+                    );
+                    for (index, planned_transfer) in planned_transfers.iter().enumerate() {
+                        println!(
+                            "{:<5}  {:<44}  {:>16}  {:>16}",
+                            index,
+                            planned_transfer.recipient,
+                            Sol(planned_transfer.lamports).to_string(),
+                            Sol(planned_transfer.fee_lamports).to_string()
+                        );
+                    }
+                }
+                println!("Transactions: {}", estimate.transactions);
+                println!("Total amount: {}", Sol(estimate.total_lamports));
+                println!("Total fees: {}", Sol(estimate.total_fee_lamports));
+                println!("Total required: {}", Sol(estimate.total_required_lamports));
+                println!(
+                    "Fee payer balance: {} ({})",
+                    Sol(estimate.balance_lamports),
+                    if estimate.sufficient {
+                        "sufficient"
+                    } else {
+                        "insufficient"
+                    }
+                );
+            }
+            OutputFormat::Json if plan => println!(
+                "{}",
+                serde_json::json!({ "transfers": planned_transfers, "estimate": estimate })
+            ),
+            OutputFormat::Json => println!("{}", serde_json::to_string(&estimate)?),
+            OutputFormat::Shell => {
+                let mut vars = vec![
+                    ("TRANSACTIONS", estimate.transactions.to_string()),
+                    ("TOTAL_LAMPORTS", estimate.total_lamports.to_string()),
+                    (
+                        "TOTAL_FEE_LAMPORTS",
+                        estimate.total_fee_lamports.to_string(),
+                    ),
+                    (
+                        "TOTAL_REQUIRED_LAMPORTS",
+                        estimate.total_required_lamports.to_string(),
+                    ),
+                    ("BALANCE_LAMPORTS", estimate.balance_lamports.to_string()),
+                    ("SUFFICIENT", estimate.sufficient.to_string()),
+                ];
+                if plan {
+                    let transfers = planned_transfers
+                        .iter()
+                        .map(|planned_transfer| {
+                            format!(
+                                "{}:{}",
+                                planned_transfer.recipient, planned_transfer.lamports
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    vars.push(("TRANSFERS", transfers.join(" ")));
+                }
+                print_shell_vars(&vars);
+            }
+        }
+    } else if count > 1 {
+        let elapsed = start.elapsed();
+        latencies.sort_by(f64::total_cmp);
+        let summary = Summary {
+            transactions: count - not_started.unwrap_or_default(),
+            succeeded,
+            failed,
+            skipped,
+            elapsed_secs: elapsed.as_secs_f64(),
+            tps: succeeded as f64 / elapsed.as_secs_f64(),
+            latency_p50_ms: percentile(&latencies, 50.),
+            latency_p90_ms: percentile(&latencies, 90.),
+            latency_p99_ms: percentile(&latencies, 99.),
+            rate_buckets: ramp
+                .as_ref()
+                .map(|ramp| {
+                    rate_buckets(
+                        &send_starts,
+                        elapsed,
+                        (ramp.duration / 10).max(Duration::from_secs(1)),
+                    )
+                })
+                .unwrap_or_default(),
+            retry_budget: config.retry_budget,
+            retries_used: config
+                .retry_budget
+                .map(|_| config.retries_used.load(Ordering::Relaxed)),
+            target_secs: spread_over.map(|spread_over| spread_over.as_secs_f64()),
+            not_started,
+        };
+        match output_format {
+            OutputFormat::Text => {
+                let mut lines = vec![format!(
+                    "Sent {} transactions ({} succeeded, {} failed, {} skipped) in {:.1}s: \
+                     {:.2} TPS",
+                    summary.transactions,
+                    summary.succeeded,
+                    summary.failed,
+                    summary.skipped,
+                    summary.elapsed_secs,
+                    summary.tps
+                )];
+                if let (Some(p50), Some(p90), Some(p99)) = (
+                    summary.latency_p50_ms,
+                    summary.latency_p90_ms,
+                    summary.latency_p99_ms,
+                ) {
+                    lines.push(format!(
+                        "Latency: p50 {p50:.1}ms, p90 {p90:.1}ms, p99 {p99:.1}ms"
+                    ));
+                }
+                if !summary.rate_buckets.is_empty() {
+                    lines.push(format!("Achieved rate: {}", format_rate_buckets(&summary)));
+                }
+                if let (Some(retry_budget), Some(retries_used)) =
+                    (summary.retry_budget, summary.retries_used)
+                {
+                    lines.push(format!(
+                        "Retries: {retries_used} of a budget of {retry_budget}"
+                    ));
+                }
+                if let Some(not_started) = summary.not_started {
+                    lines.push(format!(
+                        "Stopped at the first failure, leaving {not_started} not started"
+                    ));
+                }
+                if let Some(target_secs) = summary.target_secs {
+                    lines.push(format!(
+                        "Spread over {:.1}s, against a target of {target_secs:.1}s",
+                        summary.elapsed_secs
+                    ));
+                }
+                for line in lines {
+                    // Keep stdout to nothing but the signatures, or the template
+                    if config.signatures_only || config.template.is_some() {
+                        eprintln!("{line}");
+                    } else {
+                        println!("{line}");
+                    }
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string(&serde_json::json!({ "summary": summary }))?
+            ),
+            OutputFormat::Shell => {
+                let mut vars = vec![
+                    ("TRANSACTIONS", summary.transactions.to_string()),
+                    ("SUCCEEDED", summary.succeeded.to_string()),
+                    ("FAILED", summary.failed.to_string()),
+                    ("SKIPPED", summary.skipped.to_string()),
+                    ("ELAPSED_SECS", format!("{:.3}", summary.elapsed_secs)),
+                    ("TPS", format!("{:.2}", summary.tps)),
+                ];
+                if let (Some(p50), Some(p90), Some(p99)) = (
+                    summary.latency_p50_ms,
+                    summary.latency_p90_ms,
+                    summary.latency_p99_ms,
+                ) {
+                    vars.push(("LATENCY_P50_MS", format!("{p50:.1}")));
+                    vars.push(("LATENCY_P90_MS", format!("{p90:.1}")));
+                    vars.push(("LATENCY_P99_MS", format!("{p99:.1}")));
+                }
+                if !summary.rate_buckets.is_empty() {
+                    vars.push(("RATE_BUCKETS", format_rate_buckets(&summary)));
+                }
+                if let (Some(retry_budget), Some(retries_used)) =
+                    (summary.retry_budget, summary.retries_used)
+                {
+                    vars.push(("RETRY_BUDGET", retry_budget.to_string()));
+                    vars.push(("RETRIES_USED", retries_used.to_string()));
+                }
+                if let Some(target_secs) = summary.target_secs {
+                    vars.push(("TARGET_SECS", format!("{target_secs:.3}")));
+                }
+                if let Some(not_started) = summary.not_started {
+                    vars.push(("NOT_STARTED", not_started.to_string()));
+                }
+                print_shell_vars(&vars);
+            }
+        }
+    }
+
+    if let Some(spread_over) = spread_over {
+        let elapsed = start.elapsed();
+        if elapsed > spread_over {
+            eprintln!(
+                "warning: sending took {:.1}s, over the --spread-over target of {:.1}s",
+                elapsed.as_secs_f64(),
+                spread_over.as_secs_f64()
+            );
+        }
+    }
+
+    if config.cost_basis {
+        print_cost_basis(&config, cost_basis.into_values().collect())?;
+    }
+
+    if let Some(not_started) = not_started {
+        return Err(format!(
+            "error: stopped at the first failure, {failed} failed, {skipped} skipped and \
+             {not_started} not started of {count} transactions"
+        )
+        .into());
+    }
+    if failed > 0 {
+        return Err(format!("error: {failed} of {count} transactions failed").into());
+    }
+    Ok(())
+}
+
+/// Prints the `--cost-basis` report: what the confirmed transactions transferred and cost, per
+/// recipient and in total
+fn print_cost_basis(
+    config: &SendConfig<'_>,
+    recipients: Vec<CostBasis>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut total = CostBasis::new(String::new());
+    for cost_basis in &recipients {
+        total.add(cost_basis);
+    }
+
+    match config.output_format {
+        OutputFormat::Text => {
+            let mut lines = vec![format!(
+                "{:<44}  {:>12}  {:>24}  {:>16}  {:>16}",
+                "Recipient", "Transactions", "Transferred", "Fees", "Spent"
+            )];
+            for cost_basis in recipients.iter().chain([&total]) {
+                lines.push(format!(
+                    "{:<44}  {:>12}  {:>24}  {:>16}  {:>16}",
+                    if cost_basis.recipient.is_empty() {
+                        "Total"
+                    } else {
+                        &cost_basis.recipient
+                    },
+                    cost_basis.transactions,
+                    config.display_amount(cost_basis.transferred),
+                    Sol(cost_basis.fee_lamports).to_string(),
+                    Sol(cost_basis.spent_lamports).to_string(),
+                ));
+            }
+            for line in lines {
+                // Keep stdout to nothing but the signatures, or the template
+                if config.signatures_only || config.template.is_some() {
+                    eprintln!("{line}");
+                } else {
+                    println!("{line}");
+                }
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "cost_basis": {
+                    "recipients": recipients,
+                    "total": total,
+                }
+            })
+        ),
+        OutputFormat::Shell => print_shell_vars(&[
+            ("COST_TRANSACTIONS", total.transactions.to_string()),
+            ("COST_TRANSFERRED", total.transferred.to_string()),
+            ("COST_FEE_LAMPORTS", total.fee_lamports.to_string()),
+            ("COST_SPENT_LAMPORTS", total.spent_lamports.to_string()),
+        ]),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::system_instruction};
+
+    fn signed_transfer(keypair: &Keypair) -> Transaction {
+        let instruction = system_instruction::transfer(&keypair.pubkey(), &keypair.pubkey(), 1);
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&keypair.pubkey()),
+            &[keypair],
+            Hash::new_unique(),
+        )
+    }
+
+    #[test]
+    fn write_csv_row_quotes_fields_that_need_it() {
+        let mut csv = vec![];
+        write_csv_row(&mut csv, &["plain", "a,b", "say \"hi\"", "two\nlines", ""]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\n"
+        );
+    }
+
+    #[test]
+    fn parse_file_amount_reads_integers_and_decimals() {
+        assert_eq!(parse_file_amount("1000"), Some(FileAmount::Units(1_000)));
+        assert_eq!(parse_file_amount("0.5"), Some(FileAmount::Whole(0.5)));
+        assert_eq!(parse_file_amount("1."), Some(FileAmount::Whole(1.)));
+        assert_eq!(
+            parse_file_amount("0.5").unwrap().to_units(None),
+            500_000_000
+        );
+    }
+
+    #[test]
+    fn parse_file_amount_rejects_exponents_and_junk() {
+        for amount in [
+            "1e9", "1.5e3", "1E9", "inf", "NaN", "-1", "-0.5", "1.2.3", "0x10", "",
+        ] {
+            assert_eq!(parse_file_amount(amount), None, "{amount:?}");
+        }
+    }
+
+    #[test]
+    fn read_replay_keeps_fee_payer_and_recipient() {
+        let fee_payers = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let recipient = Pubkey::new_unique();
+        let path = std::env::temp_dir().join(format!("burri-replay-{}.ndjson", std::process::id()));
+        std::fs::write(
+            &path,
+            format!(
+                "{{\"iteration\":0,\"status\":\"confirmed\",\"fee_payer\":\"{}\",\
+                 \"recipient\":\"{recipient}\",\"lamports\":5}}\n\
+                 {{\"iteration\":1,\"status\":\"failed\",\"fee_payer\":\"{}\",\
+                 \"recipient\":\"{recipient}\",\"lamports\":6}}\n\
+                 {{\"iteration\":2,\"status\":\"failed\",\"error\":\"no balance\"}}\n",
+                fee_payers[1], fee_payers[0]
+            ),
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let transfers = read_replay(path, false, &fee_payers).unwrap();
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].signer, 1);
+        assert_eq!(transfers[0].recipient, recipient);
+        assert_eq!(transfers[0].lamports, 5);
+
+        let transfers = read_replay(path, true, &fee_payers).unwrap();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[1].signer, 0);
+
+        assert!(read_replay(path, false, &fee_payers[..1])
+            .map(|_| ())
+            .unwrap_err()
+            .to_string()
+            .contains("which isn't a signer"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn template_placeholders_finds_each_name() {
+        assert_eq!(
+            template_placeholders("{signature} in {slot}, {").collect::<Vec<_>>(),
+            ["signature", "slot"]
+        );
+        assert!(is_output_template("{signature} {amount_sol}").is_ok());
+        assert!(is_output_template("no placeholders").is_ok());
+        assert!(is_output_template("{signature} {memo}")
+            .unwrap_err()
+            .starts_with("unknown placeholder {memo}"));
+    }
+
+    #[test]
+    fn is_positive_number_rejects_zero_and_negatives() {
+        assert!(is_positive_number("1e-300").is_ok());
+        assert!(is_positive_number("0").is_err());
+        assert!(is_positive_number("-1").is_err());
+        assert!(is_positive_number("fast").is_err());
+    }
+
+    #[test]
+    fn escalate_price_always_goes_up() {
+        assert_eq!(escalate_price(None, 1.5), 1);
+        assert_eq!(escalate_price(Some(0), 1.5), 1);
+        assert_eq!(escalate_price(Some(1), 1.5), 2);
+        assert_eq!(escalate_price(Some(2), 1.5), 3);
+        assert_eq!(escalate_price(Some(1_000), 2.), 2_000);
+        assert_eq!(escalate_price(Some(u64::MAX), 2.), u64::MAX);
+    }
+
+    #[test]
+    fn verify_signatures_rejects_wrong_signer() {
+        let keypair = Keypair::new();
+        let mut transaction = signed_transfer(&keypair);
+        assert_eq!(verify_signatures(&transaction), Ok(()));
+
+        // Signed by another keypair, as a signer for the wrong key would
+        transaction.signatures[0] = Keypair::new().sign_message(&transaction.message_data());
+        assert_eq!(
+            verify_signatures(&transaction),
+            Err("error: transaction failed local signature verification: \
+                 Transaction did not pass signature verification"
+                .to_string())
+        );
+    }
+
+    #[test]
+    fn verify_signatures_rejects_bogus_signature() {
+        let mut transaction = signed_transfer(&Keypair::new());
+        transaction.signatures[0] = Signature::new(&[7; 64]);
+        assert!(verify_signatures(&transaction)
+            .unwrap_err()
+            .starts_with("error: transaction failed local signature verification"));
+    }
+}
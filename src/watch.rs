@@ -0,0 +1,146 @@
+use {
+    crate::{print_shell_vars, Context, OutputFormat},
+    clap::{Arg, ArgMatches, Command},
+    solana_clap_v3_utils::{
+        input_parsers::{pubkeys_of, value_of},
+        input_validators::{is_parsable, is_valid_pubkey},
+    },
+    solana_remote_wallet::remote_wallet::RemoteWalletManager,
+    solana_sdk::{commitment_config::CommitmentConfig, native_token::Sol},
+    std::{
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+pub fn command() -> Command<'static> {
+    Command::new("watch")
+        .about("Print the fee payer's balance, and those of any extra addresses, until interrupted")
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("5")
+                .validator(|s| match s.parse::<f64>() {
+                    // Rules out infinity and intervals too long to wait for
+                    Ok(interval) if interval > 0. => Duration::try_from_secs_f64(interval)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string()),
+                    Ok(_) => Err("must be greater than zero".to_string()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .help("Time between polls"),
+        )
+        .arg(
+            Arg::new("commitment")
+                .long("commitment")
+                .value_name("COMMITMENT")
+                .takes_value(true)
+                .possible_values(["processed", "confirmed", "finalized"])
+                .default_value("confirmed")
+                .help("Commitment of the balances"),
+        )
+        .arg(
+            Arg::new("count")
+                .long("count")
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help("Stop after N polls rather than when interrupted"),
+        )
+        .arg(
+            Arg::new("extra_addresses")
+                .value_name("ADDRESS")
+                .validator(|s| is_valid_pubkey(s))
+                .takes_value(true)
+                .multiple(true)
+                .help("Extra addresses to watch"),
+        )
+}
+
+/// Polls the balances at `--interval` and prints each snapshot, one NDJSON object per poll with
+/// `--output json`. The balances of a snapshot are all read at the same slot
+pub async fn run(
+    context: &Context,
+    matches: &ArgMatches,
+    wallet_manager: &mut Option<Arc<RemoteWalletManager>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Only the fee payer's address is needed
+    let mut addresses = vec![context.signer(matches, wallet_manager, true)?.pubkey()];
+    addresses.extend(pubkeys_of(matches, "extra_addresses").unwrap_or_default());
+    let interval = Duration::from_secs_f64(value_of(matches, "interval").unwrap());
+    let count = value_of::<u64>(matches, "count");
+    let rpc_client = context
+        .rpc_client_with_commitment(value_of::<CommitmentConfig>(matches, "commitment").unwrap());
+
+    let mut polls = 0;
+    loop {
+        let response = rpc_client
+            .call("getMultipleAccounts", |rpc_client| {
+                rpc_client
+                    .get_multiple_accounts_with_commitment(&addresses, rpc_client.commitment())
+            })
+            .await
+            .map_err(|err| format!("error: unable to get balances: {err}"))?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // An address with no account has no balance
+        let balances = addresses
+            .iter()
+            .zip(&response.value)
+            .map(|(address, account)| {
+                (
+                    address,
+                    account.as_ref().map_or(0, |account| account.lamports),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        match context.output_format {
+            OutputFormat::Text => {
+                println!("Slot {} at {timestamp}:", response.context.slot);
+                for (address, lamports) in &balances {
+                    println!("  {address}: {}", Sol(*lamports));
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::json!({
+                    "timestamp": timestamp,
+                    "slot": response.context.slot,
+                    "balances": balances
+                        .iter()
+                        .map(|(address, lamports)| serde_json::json!({
+                            "address": address.to_string(),
+                            "lamports": lamports,
+                        }))
+                        .collect::<Vec<_>>(),
+                })
+            ),
+            OutputFormat::Shell => print_shell_vars(&[
+                ("TIMESTAMP", timestamp.to_string()),
+                ("SLOT", response.context.slot.to_string()),
+                (
+                    "BALANCES",
+                    balances
+                        .iter()
+                        .map(|(address, lamports)| format!("{address}:{lamports}"))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            ]),
+        }
+
+        polls += 1;
+        if count.is_some_and(|count| polls >= count) {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use {
+    solana_sdk::signature::{keypair_from_seed, write_keypair_file},
+    std::process::Command,
+};
+
+/// `send --sign-only` with a fixed keypair, amount and blockhash, base64-encoded. Signing is
+/// deterministic, so these bytes never change
+const EXPECTED_TRANSACTION: &str = "AW8XQSAqbLgO1dvjLCVUUnyl/8SO60QBp2fQTIpt47XIgW2t5ugjqZX4S/eKBwFSqLP600GX8uhib15HdBZeKgUBAAECGX9rI+FshTLGq8g4+s1ep4m+DHaykgM0A5v6iz02jWEAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAEAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAQECAAAMAgAAAOgDAAAAAAAA";
+
+#[test]
+fn sign_only_prints_the_known_transaction() {
+    let keypair_path =
+        std::env::temp_dir().join(format!("burri-sign-only-{}.json", std::process::id()));
+    write_keypair_file(&keypair_from_seed(&[42; 32]).unwrap(), &keypair_path).unwrap();
+
+    // Nothing on the sign-only path reaches the network, which the unroutable URL confirms
+    let output = Command::new(env!("CARGO_BIN_EXE_burri"))
+        .args(["--url", "http://127.0.0.1:1", "--keypair"])
+        .arg(&keypair_path)
+        .args([
+            "--output",
+            "json",
+            "send",
+            "--sign-only",
+            "--amount",
+            "0.000001",
+            "--blockhash",
+            "4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM",
+        ])
+        .output()
+        .unwrap();
+    std::fs::remove_file(&keypair_path).unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let printed = serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+    assert_eq!(printed["transaction"], EXPECTED_TRANSACTION);
+}